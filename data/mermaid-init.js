@@ -0,0 +1,53 @@
+// Draws ```mermaid blocks with the bundled mermaid.js.
+//
+// Runs after mermaid.min.js in the preview and in exported pages. The theme
+// follows the page background, so light, dark and custom stylesheets all
+// get readable diagrams.
+(function () {
+    "use strict";
+
+    function isDark() {
+        var color = window.getComputedStyle(document.body).backgroundColor;
+        var parts = (color.match(/[\d.]+/g) || []).map(Number);
+        // A transparent page shows the default white background
+        if (parts.length < 3 || parts[3] === 0) {
+            return false;
+        }
+        return 0.2126 * parts[0] + 0.7152 * parts[1] + 0.0722 * parts[2] < 128;
+    }
+
+    function draw(nodes) {
+        if (nodes.length === 0) {
+            return;
+        }
+        // mermaid replaces the source with the SVG; keep it for redrawing
+        for (var i = 0; i < nodes.length; i++) {
+            if (nodes[i].dataset.source === undefined) {
+                nodes[i].dataset.source = nodes[i].textContent;
+            }
+        }
+        window.mermaid.initialize({ startOnLoad: false, theme: isDark() ? "dark" : "default" });
+        window.mermaid.run({ nodes: nodes }).catch(function () {});
+    }
+
+    window.mymdMermaid = {
+        // Draws the diagrams under `root` that are not drawn yet
+        draw: function (root) {
+            draw(root.querySelectorAll(".mermaid:not([data-processed])"));
+        },
+
+        // Draws every diagram again, after the page colors changed
+        redraw: function () {
+            var nodes = document.querySelectorAll(".mermaid");
+            for (var i = 0; i < nodes.length; i++) {
+                if (nodes[i].dataset.source !== undefined) {
+                    nodes[i].textContent = nodes[i].dataset.source;
+                    nodes[i].removeAttribute("data-processed");
+                }
+            }
+            draw(nodes);
+        }
+    };
+
+    window.mymdMermaid.draw(document);
+})();
@@ -0,0 +1,162 @@
+// Preview side of the editor <-> preview bridge.
+//
+// Injected into every page the preview loads. Blocks carry the source line
+// they start on in `data-line`; messages go back to the editor through the
+// "mymd" script message handler as "name:argument" strings.
+(function () {
+    "use strict";
+
+    // Scroll events caused by the editor must not be echoed back to it
+    var ignoreScrollUntil = 0;
+    var scrollQueued = false;
+
+    function post(message) {
+        window.webkit.messageHandlers.mymd.postMessage(message);
+    }
+
+    // Top of each block in page coordinates, in document order
+    function anchors() {
+        var nodes = document.querySelectorAll("[data-line]");
+        var list = [];
+        for (var i = 0; i < nodes.length; i++) {
+            list.push({
+                line: Number(nodes[i].dataset.line),
+                top: nodes[i].getBoundingClientRect().top + window.scrollY
+            });
+        }
+        return list;
+    }
+
+    // Y offset of the (fractional, 1-based) source line
+    function offsetForLine(line) {
+        var list = anchors();
+        for (var i = list.length - 1; i >= 0; i--) {
+            if (list[i].line <= line) {
+                var next = list[i + 1];
+                if (!next) {
+                    return list[i].top;
+                }
+                var ratio = (line - list[i].line) / (next.line - list[i].line);
+                return list[i].top + ratio * (next.top - list[i].top);
+            }
+        }
+        return 0;
+    }
+
+    // Source line shown at the given Y offset
+    function lineForOffset(y) {
+        var list = anchors();
+        for (var i = list.length - 1; i >= 0; i--) {
+            if (list[i].top <= y) {
+                var next = list[i + 1];
+                if (!next || next.top === list[i].top) {
+                    return list[i].line;
+                }
+                var ratio = (y - list[i].top) / (next.top - list[i].top);
+                return list[i].line + Math.min(ratio, 1) * (next.line - list[i].line);
+            }
+        }
+        return 1;
+    }
+
+    window.addEventListener("scroll", function () {
+        if (Date.now() < ignoreScrollUntil || scrollQueued) {
+            return;
+        }
+        scrollQueued = true;
+        window.requestAnimationFrame(function () {
+            scrollQueued = false;
+            post("scroll:" + lineForOffset(window.scrollY));
+        });
+    });
+
+    // Double-click jumps to the block's source, next to the selected word
+    document.addEventListener("dblclick", function (event) {
+        var block = event.target.closest ? event.target.closest("[data-line]") : null;
+        if (!block) {
+            return;
+        }
+        var word = String(window.getSelection()).trim();
+        post("goto:" + block.dataset.line + ":" + word);
+    });
+
+    // Task list checkboxes are rendered disabled so exported pages stay
+    // inert; in the preview they toggle the marker in the source
+    function taskBoxes() {
+        return document.querySelectorAll("input.task-list-item-checkbox");
+    }
+
+    function enableTasks(root) {
+        var boxes = root.querySelectorAll("input.task-list-item-checkbox");
+        for (var i = 0; i < boxes.length; i++) {
+            boxes[i].disabled = false;
+        }
+    }
+
+    document.addEventListener("change", function (event) {
+        var box = event.target;
+        if (!box.classList || !box.classList.contains("task-list-item-checkbox")) {
+            return;
+        }
+        var index = Array.prototype.indexOf.call(taskBoxes(), box);
+        post("task:" + index + ":" + (box.checked ? 1 : 0));
+    });
+
+    enableTasks(document);
+
+    // mermaid-init.js only draws the diagrams present at load on its own
+    function drawDiagrams(root) {
+        if (window.mymdMermaid) {
+            window.mymdMermaid.draw(root);
+        }
+    }
+
+    function blockNodes() {
+        return document.querySelectorAll("body > [data-line]");
+    }
+
+    window.mymd = {
+        // Swaps the page stylesheet when the theme or light/dark style changes
+        setStylesheet: function (css) {
+            var style = document.getElementById("mymd-stylesheet");
+            if (style) {
+                style.textContent = css;
+            }
+            // Mermaid bakes the colors into its SVG
+            if (window.mymdMermaid) {
+                window.mymdMermaid.redraw();
+            }
+        },
+
+        scrollToLine: function (line) {
+            ignoreScrollUntil = Date.now() + 100;
+            window.scrollTo(0, offsetForLine(line));
+        },
+
+        // Replaces `removed` blocks starting at `start` with `blocks`, then
+        // renumbers every block with its source line from `lines`
+        patch: function (start, removed, blocks, lines) {
+            var nodes = blockNodes();
+            var before = nodes[start + removed] || null;
+            var i;
+
+            for (i = start; i < start + removed && i < nodes.length; i++) {
+                nodes[i].remove();
+            }
+
+            for (i = 0; i < blocks.length; i++) {
+                var node = document.createElement("div");
+                node.dataset.line = blocks[i].line;
+                node.innerHTML = blocks[i].html;
+                enableTasks(node);
+                drawDiagrams(node);
+                document.body.insertBefore(node, before);
+            }
+
+            nodes = blockNodes();
+            for (i = 0; i < nodes.length && i < lines.length; i++) {
+                nodes[i].dataset.line = lines[i];
+            }
+        }
+    };
+})();
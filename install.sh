@@ -13,11 +13,13 @@ echo ""
 # Check if running as root for system-wide install
 INSTALL_DIR="/usr/local/bin"
 DESKTOP_DIR="/usr/share/applications"
+SCHEMA_DIR="/usr/local/share/glib-2.0/schemas"
 
 if [ "$EUID" -ne 0 ]; then
     echo -e "${YELLOW}Note: Running without root. Will install to ~/.local instead.${NC}"
     INSTALL_DIR="$HOME/.local/bin"
     DESKTOP_DIR="$HOME/.local/share/applications"
+    SCHEMA_DIR="$HOME/.local/share/glib-2.0/schemas"
     mkdir -p "$INSTALL_DIR" "$DESKTOP_DIR"
 fi
 
@@ -84,6 +86,16 @@ chmod 755 "$INSTALL_DIR/mymd"
 echo -e "${YELLOW}Installing desktop file to $DESKTOP_DIR...${NC}"
 cp data/org.gnome.MyMarkdown.desktop "$DESKTOP_DIR/"
 
+# Install the settings schema, which keeps preferences between runs
+echo -e "${YELLOW}Installing settings schema to $SCHEMA_DIR...${NC}"
+mkdir -p "$SCHEMA_DIR"
+cp data/org.gnome.MyMarkdown.gschema.xml "$SCHEMA_DIR/"
+if command -v glib-compile-schemas &> /dev/null; then
+    glib-compile-schemas "$SCHEMA_DIR"
+else
+    echo -e "${YELLOW}glib-compile-schemas not found; preferences will not be saved.${NC}"
+fi
+
 # Update desktop database
 if command -v update-desktop-database &> /dev/null; then
     update-desktop-database "$DESKTOP_DIR" 2>/dev/null || true
@@ -1,3 +1,4 @@
+use crate::settings::{self, Preferences};
 use crate::window::MyMarkdownWindow;
 use adw::prelude::*;
 use adw::subclass::prelude::*;
@@ -10,8 +11,10 @@ mod imp {
 
     #[derive(Default)]
     pub struct MyMarkdownApp {
-        pub file_arg: RefCell<Option<String>>,
         pub initial_dir: RefCell<PathBuf>,
+        /// `None` when the settings schema is not installed.
+        pub settings: RefCell<Option<gio::Settings>>,
+        pub preferences: RefCell<Preferences>,
     }
 
     #[glib::object_subclass]
@@ -24,12 +27,58 @@ mod imp {
     impl ObjectImpl for MyMarkdownApp {}
 
     impl ApplicationImpl for MyMarkdownApp {
+        fn shutdown(&self) {
+            // Windows still open here are quitting together; after the last
+            // window closed on its own there are none and the session it
+            // saved stays.
+            self.obj().save_session();
+            self.parent_shutdown();
+        }
+
         fn activate(&self) {
             let app = self.obj();
-            let file_arg = self.file_arg.borrow().clone();
+            // Only the first window picks up the last session
+            let first_window = app.windows().is_empty();
             let initial_dir = self.initial_dir.borrow().clone();
-            let window = MyMarkdownWindow::new(&app, file_arg, initial_dir);
+            let window = MyMarkdownWindow::new(&app, initial_dir);
+            let restored =
+                first_window && app.preferences().restore_session && window.restore_session();
+            if !restored {
+                window.new_file();
+            }
+            window.present();
+            if first_window {
+                window.offer_recovery();
+            }
+        }
+
+        fn open(&self, files: &[gio::File], _hint: &str) {
+            // Called for files given on the command line or by the desktop
+            // launcher, including those forwarded from a second instance.
+            // They open as tabs in the active window.
+            let app = self.obj();
+            let first_window = app.windows().is_empty();
+            let window = match app.active_window().and_downcast::<MyMarkdownWindow>() {
+                Some(window) => window,
+                None => {
+                    let initial_dir = self.initial_dir.borrow().clone();
+                    MyMarkdownWindow::new(&app, initial_dir)
+                }
+            };
+
+            for file in files {
+                if let Some(path) = file.path() {
+                    window.open_path(path);
+                }
+            }
+            // A new window whose files all failed to open gets an empty tab
+            if window.documents().is_empty() {
+                window.new_file();
+            }
             window.present();
+            if first_window {
+                window.offer_recovery();
+            }
         }
     }
 
@@ -44,14 +93,98 @@ glib::wrapper! {
 }
 
 impl MyMarkdownApp {
-    pub fn new(file_arg: Option<String>, initial_dir: PathBuf) -> Self {
+    pub fn new(initial_dir: PathBuf) -> Self {
         let app: Self = glib::Object::builder()
             .property("application-id", "org.gnome.MyMarkdown")
-            .property("flags", gio::ApplicationFlags::FLAGS_NONE)
+            .property("flags", gio::ApplicationFlags::HANDLES_OPEN)
             .build();
 
-        app.imp().file_arg.replace(file_arg);
         app.imp().initial_dir.replace(initial_dir);
+        app.load_settings();
         app
     }
+
+    fn load_settings(&self) {
+        let Some(settings) = settings::settings() else {
+            return;
+        };
+        self.imp().preferences.replace(Preferences::load(&settings));
+
+        // Pick up changes made elsewhere, e.g. with the gsettings tool
+        let app = self.downgrade();
+        settings.connect_changed(None, move |settings, _| {
+            let Some(app) = app.upgrade() else {
+                return;
+            };
+            let preferences = Preferences::load(settings);
+            if preferences != *app.imp().preferences.borrow() {
+                app.imp().preferences.replace(preferences);
+                app.apply_preferences();
+            }
+        });
+        self.imp().settings.replace(Some(settings));
+    }
+
+    /// The app settings, `None` when the schema is not installed.
+    pub fn settings(&self) -> Option<gio::Settings> {
+        self.imp().settings.borrow().clone()
+    }
+
+    pub fn preferences(&self) -> Preferences {
+        self.imp().preferences.borrow().clone()
+    }
+
+    /// Changes the preferences, saves them and applies them to every window.
+    pub fn update_preferences<F>(&self, update: F)
+    where
+        F: FnOnce(&mut Preferences),
+    {
+        let mut preferences = self.preferences();
+        update(&mut preferences);
+        if preferences == *self.imp().preferences.borrow() {
+            return;
+        }
+
+        self.imp().preferences.replace(preferences.clone());
+        // Without the schema there is nowhere to save to
+        if self.imp().settings.borrow().is_some() {
+            preferences.save();
+        }
+        self.apply_preferences();
+    }
+
+    /// Saves the files open in every window, to reopen them next time.
+    pub fn save_session(&self) {
+        let Some(ref settings) = *self.imp().settings.borrow() else {
+            return;
+        };
+        let windows: Vec<MyMarkdownWindow> = self
+            .windows()
+            .into_iter()
+            .filter_map(|window| window.downcast::<MyMarkdownWindow>().ok())
+            .collect();
+        if windows.is_empty() {
+            return;
+        }
+
+        let files: Vec<String> = windows
+            .iter()
+            .flat_map(|window| window.documents())
+            .filter_map(|document| document.file())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+        if let Err(e) = settings.set_strv("session-files", files.as_slice()) {
+            eprintln!("Error saving session: {}", e);
+        }
+    }
+
+    /// Restyles every open window after a preference changed.
+    fn apply_preferences(&self) {
+        for window in self.windows() {
+            if let Ok(window) = window.downcast::<MyMarkdownWindow>() {
+                window.apply_preferences();
+            }
+        }
+    }
 }
@@ -0,0 +1,256 @@
+use crate::settings::{self, Preferences};
+use mymd::highlight;
+use mymd::render::Renderer;
+use mymd::theme::PreviewTheme;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage:
+  mymd [FILE...]                              Open each FILE in the editor
+  mymd render [FILE|-]                        Print the rendered HTML body
+  mymd export FILE|- [OPTIONS]                Write a standalone HTML page
+
+Export options:
+  --to html                 Output format; only html is supported
+  -o, --output OUTPUT       Write the page to OUTPUT
+  --theme light|dark|CSS    Bundled light or dark stylesheet, or a CSS file
+  --style-scheme ID         Editor color scheme to color code blocks with
+  --preferences             Look like the preview: use the editor preferences
+                            and the desktop's light or dark style
+
+Use - to read from standard input. Without -o, export writes next to the
+input file (FILE.html), or to standard output when reading from stdin. The
+input file is never overwritten.
+
+By default pages use the light stylesheet, so the same input always gives
+the same page. --theme and --style-scheme also apply with --preferences.";
+
+/// Headless subcommands that run without starting the GTK application.
+#[derive(Debug)]
+pub enum Command {
+    Render { input: Input },
+    Export { input: Input, output: Output, style: Style },
+    Help,
+}
+
+#[derive(Debug)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Look of an exported page: the bundled light style unless given, or the
+/// preview's look with `preferences`.
+#[derive(Debug, Default)]
+pub struct Style {
+    pub theme: Option<ThemeChoice>,
+    /// GtkSourceView style scheme id.
+    pub style_scheme: Option<String>,
+    /// Fill in what is not given from the editor preferences and the
+    /// desktop's light or dark style, the same way the preview does.
+    pub preferences: bool,
+}
+
+#[derive(Debug)]
+pub enum ThemeChoice {
+    Light,
+    Dark,
+    Custom(PathBuf),
+}
+
+impl Style {
+    fn renderer(&self) -> Renderer {
+        // Scripts and CI get the same page on every machine by default
+        let preferences = if self.preferences {
+            settings::saved_preferences().unwrap_or_default()
+        } else {
+            Preferences::default()
+        };
+        let system_dark = self.preferences && settings::prefers_dark();
+        let (theme, dark) = match &self.theme {
+            Some(ThemeChoice::Light) => (PreviewTheme::System, false),
+            Some(ThemeChoice::Dark) => (PreviewTheme::System, true),
+            Some(ThemeChoice::Custom(path)) => (PreviewTheme::Custom(path.clone()), system_dark),
+            None => (preferences.preview_theme, system_dark),
+        };
+        let style_scheme = self.style_scheme.clone().or(preferences.style_scheme);
+
+        Renderer::new()
+            .with_stylesheet(theme.stylesheet(dark))
+            .with_code_theme(highlight::theme_for_preferences(style_scheme.as_deref(), dark))
+    }
+}
+
+impl Command {
+    /// Parses the arguments following the program name.
+    ///
+    /// Returns `None` when the arguments do not start with a subcommand, in
+    /// which case they are meant for the editor.
+    pub fn parse(args: &[String]) -> Option<Result<Self, String>> {
+        let (name, rest) = args.split_first()?;
+        match name.as_str() {
+            "render" => Some(parse_render(rest)),
+            "export" => Some(parse_export(rest)),
+            "-h" | "--help" | "help" => Some(Ok(Command::Help)),
+            _ => None,
+        }
+    }
+
+    /// Runs the command and returns the process exit code.
+    pub fn run(self) -> i32 {
+        match self.execute() {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("mymd: {}", e);
+                1
+            }
+        }
+    }
+
+    fn execute(self) -> Result<(), String> {
+        match self {
+            Command::Help => {
+                println!("{}", USAGE);
+                Ok(())
+            }
+            Command::Render { input } => {
+                // Highlighted code only carries classes, so no theme is needed
+                let markdown = read_input(&input)?;
+                write_output(&Output::Stdout, &Renderer::new().render_body(&markdown))
+            }
+            Command::Export { input, output, style } => {
+                let markdown = read_input(&input)?;
+                write_output(&output, &style.renderer().render(&markdown))
+            }
+        }
+    }
+}
+
+/// Prints the usage text after a parse error and returns the exit code.
+pub fn usage_error(message: &str) -> i32 {
+    eprintln!("mymd: {}\n\n{}", message, USAGE);
+    2
+}
+
+fn parse_input(arg: &str) -> Input {
+    if arg == "-" {
+        Input::Stdin
+    } else {
+        Input::File(PathBuf::from(arg))
+    }
+}
+
+fn parse_render(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Render { input: Input::Stdin }),
+        [input] => Ok(Command::Render { input: parse_input(input) }),
+        _ => Err("render takes at most one input file".to_string()),
+    }
+}
+
+fn parse_export(args: &[String]) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut style = Style::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--to" | "-t" => {
+                let format = iter.next().ok_or("--to needs a format")?;
+                if format != "html" {
+                    return Err(format!("unsupported export format '{}'", format));
+                }
+            }
+            "-o" | "--output" => {
+                let path = iter.next().ok_or("-o needs an output file")?;
+                output = Some(if path == "-" {
+                    Output::Stdout
+                } else {
+                    Output::File(PathBuf::from(path))
+                });
+            }
+            "--theme" => {
+                let theme = iter.next().ok_or("--theme needs light, dark or a CSS file")?;
+                style.theme = Some(match theme.as_str() {
+                    "light" => ThemeChoice::Light,
+                    "dark" => ThemeChoice::Dark,
+                    path if Path::new(path).is_file() => ThemeChoice::Custom(PathBuf::from(path)),
+                    path => return Err(format!("theme '{}' is not light, dark or a CSS file", path)),
+                });
+            }
+            "--style-scheme" => {
+                let id = iter.next().ok_or("--style-scheme needs a scheme id")?;
+                style.style_scheme = Some(id.clone());
+            }
+            "--preferences" => style.preferences = true,
+            _ if input.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                input = Some(parse_input(arg));
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let input = input.ok_or("export needs an input file")?;
+    let output = output.unwrap_or_else(|| match &input {
+        Input::Stdin => Output::Stdout,
+        Input::File(path) => Output::File(default_output(path)),
+    });
+    if let (Input::File(input), Output::File(output)) = (&input, &output)
+        && same_file(input, output)
+    {
+        return Err(format!("{} is the input file; pick another output with -o", output.display()));
+    }
+
+    Ok(Command::Export { input, output, style })
+}
+
+/// `notes.md` exports to `notes.html`, and `page.html` to `page.export.html`
+/// so the input is not overwritten.
+fn default_output(input: &Path) -> PathBuf {
+    let output = input.with_extension("html");
+    if output == input {
+        input.with_extension("export.html")
+    } else {
+        output
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn read_input(input: &Input) -> Result<String, String> {
+    match input {
+        Input::Stdin => {
+            let mut markdown = String::new();
+            io::stdin()
+                .read_to_string(&mut markdown)
+                .map_err(|e| format!("cannot read standard input: {}", e))?;
+            Ok(markdown)
+        }
+        Input::File(path) => fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+fn write_output(output: &Output, html: &str) -> Result<(), String> {
+    match output {
+        Output::Stdout => io::stdout()
+            .write_all(html.as_bytes())
+            .map_err(|e| format!("cannot write standard output: {}", e)),
+        Output::File(path) => fs::write(path, html)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e)),
+    }
+}
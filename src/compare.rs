@@ -0,0 +1,88 @@
+//! Side-by-side view of a document and another version of its text, such as
+//! its file on disk or an autosaved copy.
+
+use adw::prelude::*;
+use gtk::gdk;
+use mymd::diff;
+use sourceview::prelude::*;
+
+/// Builds a dialog with the `(heading, text)` of the old version on the left
+/// and of the new one on the right, the lines that differ marked on both sides.
+pub fn dialog(
+    title: &str,
+    (old_heading, old): (&str, &str),
+    (new_heading, new): (&str, &str),
+    style_scheme: Option<&sourceview::StyleScheme>,
+) -> adw::Dialog {
+    let (removed, added) = diff::changed_lines(old, new);
+    let removed_color = gdk::RGBA::new(0.88, 0.11, 0.14, 0.2);
+    let added_color = gdk::RGBA::new(0.18, 0.76, 0.49, 0.2);
+
+    let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+    paned.set_shrink_start_child(false);
+    paned.set_shrink_end_child(false);
+    paned.set_start_child(Some(&column(old_heading, old, &removed, &removed_color, style_scheme)));
+    paned.set_end_child(Some(&column(new_heading, new, &added, &added_color, style_scheme)));
+
+    let toolbar = adw::ToolbarView::new();
+    toolbar.add_top_bar(&adw::HeaderBar::new());
+    toolbar.set_content(Some(&paned));
+
+    adw::Dialog::builder()
+        .title(title)
+        .content_width(1000)
+        .content_height(600)
+        .child(&toolbar)
+        .build()
+}
+
+/// One read-only side, with a heading above it.
+fn column(
+    heading: &str,
+    text: &str,
+    changed: &[usize],
+    color: &gdk::RGBA,
+    style_scheme: Option<&sourceview::StyleScheme>,
+) -> gtk::Box {
+    let buffer = sourceview::Buffer::new(None);
+    if let Some(language) = sourceview::LanguageManager::default().language("markdown") {
+        buffer.set_language(Some(&language));
+    }
+    buffer.set_style_scheme(style_scheme);
+    buffer.set_text(text);
+
+    let tag = gtk::TextTag::builder().paragraph_background_rgba(color).build();
+    buffer.tag_table().add(&tag);
+    for &line in changed {
+        if let Some(start) = buffer.iter_at_line(line as i32) {
+            let end = buffer
+                .iter_at_line(line as i32 + 1)
+                .unwrap_or_else(|| buffer.end_iter());
+            buffer.apply_tag(&tag, &start, &end);
+        }
+    }
+
+    let view = sourceview::View::with_buffer(&buffer);
+    view.set_editable(false);
+    view.set_monospace(true);
+    view.set_show_line_numbers(true);
+    view.set_wrap_mode(gtk::WrapMode::WordChar);
+    view.set_left_margin(6);
+    view.set_right_margin(6);
+
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_vexpand(true);
+    scrolled.set_hexpand(true);
+    scrolled.set_child(Some(&view));
+
+    let label = gtk::Label::new(Some(heading));
+    label.add_css_class("heading");
+    label.set_margin_top(6);
+    label.set_margin_bottom(6);
+
+    let column = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    column.append(&label);
+    column.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    column.append(&scrolled);
+    column
+}
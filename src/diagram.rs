@@ -0,0 +1,473 @@
+//! Diagram fences (```dot, ```plantuml, ```mermaid).
+//!
+//! Each diagram language is handled by a [`DiagramBackend`]. Backends that
+//! are not installed are skipped and their fences render as ordinary code
+//! blocks. Results are cached by content hash, since the preview re-renders
+//! the whole document on every edit.
+//!
+//! Backends that run a program are slow, so the preview does not wait for
+//! them: see [`Diagrams::render_deferred`].
+
+use crate::render::escape_html;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of rendered diagrams kept before the cache is cleared.
+const CACHE_LIMIT: usize = 64;
+
+/// How long a diagram program may run before it is killed. PlantUML starts
+/// a JVM, which takes a few seconds on its own.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// mermaid.js, followed by the code that draws ```mermaid blocks with it.
+const MERMAID_SCRIPT: &str = concat!(
+    include_str!("../data/mermaid.min.js"),
+    "\n",
+    include_str!("../data/mermaid-init.js")
+);
+
+/// Turns the source of a diagram fence into HTML.
+pub trait DiagramBackend: Send + Sync {
+    /// Fence languages this backend handles.
+    fn languages(&self) -> &[&str];
+
+    /// Whether the tools the backend needs are installed.
+    fn is_available(&self) -> bool;
+
+    /// Renders `source`, or returns an error message to show in its place.
+    fn render(&self, source: &str) -> Result<String, String>;
+
+    /// Whether rendering can take long, as when a program has to start.
+    /// The preview renders slow backends in the background.
+    fn is_slow(&self) -> bool {
+        false
+    }
+
+    /// Script the rendered page needs, for backends that draw client-side.
+    fn script(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Renders diagrams to SVG with an external program reading the diagram
+/// source on stdin, such as Graphviz `dot`.
+pub struct CommandBackend {
+    languages: Vec<&'static str>,
+    program: &'static str,
+    args: Vec<&'static str>,
+    available: OnceLock<bool>,
+}
+
+impl CommandBackend {
+    pub fn new(languages: &[&'static str], program: &'static str, args: &[&'static str]) -> Self {
+        Self {
+            languages: languages.to_vec(),
+            program,
+            args: args.to_vec(),
+            available: OnceLock::new(),
+        }
+    }
+
+    /// Graphviz, for ```dot and ```graphviz fences.
+    pub fn graphviz() -> Self {
+        Self::new(&["dot", "graphviz"], "dot", &["-Tsvg"])
+    }
+
+    /// PlantUML, for ```plantuml fences.
+    pub fn plantuml() -> Self {
+        Self::new(&["plantuml", "puml"], "plantuml", &["-tsvg", "-pipe"])
+    }
+}
+
+impl DiagramBackend for CommandBackend {
+    fn languages(&self) -> &[&str] {
+        &self.languages
+    }
+
+    fn is_available(&self) -> bool {
+        *self.available.get_or_init(|| find_program(self.program).is_some())
+    }
+
+    fn render(&self, source: &str) -> Result<String, String> {
+        let mut child = Command::new(self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", self.program, e))?;
+
+        // Pipes are fed and drained from other threads so a large graph
+        // cannot deadlock against a full pipe while we wait
+        let mut stdin = child.stdin.take().ok_or("no stdin")?;
+        let input = source.to_string();
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let deadline = Instant::now() + COMMAND_TIMEOUT;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!(
+                        "{} did not finish within {} seconds",
+                        self.program,
+                        COMMAND_TIMEOUT.as_secs()
+                    ));
+                }
+                Err(e) => return Err(format!("{} failed: {}", self.program, e)),
+            }
+        };
+
+        if !status.success() {
+            let message = String::from_utf8_lossy(&stderr.join().unwrap_or_default()).into_owned();
+            return Err(format!("{}: {}", self.program, message.trim()));
+        }
+
+        let stdout = stdout.join().unwrap_or_default();
+        let svg = String::from_utf8_lossy(&stdout);
+        // Drop the XML declaration and doctype, which are invalid inside HTML
+        match svg.find("<svg") {
+            Some(start) => Ok(svg[start..].to_string()),
+            None => Err(format!("{} produced no SVG", self.program)),
+        }
+    }
+
+    fn is_slow(&self) -> bool {
+        true
+    }
+}
+
+/// Reads `pipe` to the end on another thread.
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Mermaid, drawn in the page by the bundled mermaid.js.
+///
+/// Diagrams take the light or dark mermaid theme from the page background,
+/// and are drawn again when the preview stylesheet changes.
+#[derive(Default)]
+pub struct MermaidBackend;
+
+impl DiagramBackend for MermaidBackend {
+    fn languages(&self) -> &[&str] {
+        &["mermaid"]
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn render(&self, source: &str) -> Result<String, String> {
+        Ok(format!("<pre class=\"mermaid\">{}</pre>", escape_html(source)))
+    }
+
+    fn script(&self) -> Option<&str> {
+        Some(MERMAID_SCRIPT)
+    }
+}
+
+/// The diagram backends a renderer uses, with their render cache.
+pub struct Diagrams {
+    backends: Vec<Box<dyn DiagramBackend>>,
+    cache: Mutex<HashMap<u64, String>>,
+    /// Slow diagrams waiting for [`Diagrams::take_pending`]: cache key,
+    /// language and source.
+    pending: Mutex<Vec<(u64, String, String)>>,
+    /// Keys of the slow diagrams queued or being rendered.
+    queued: Mutex<HashSet<u64>>,
+}
+
+impl Diagrams {
+    /// An empty registry; diagram fences render as code.
+    pub fn new() -> Self {
+        Self {
+            backends: Vec::new(),
+            cache: Mutex::new(HashMap::new()),
+            pending: Mutex::new(Vec::new()),
+            queued: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Graphviz, PlantUML and Mermaid.
+    pub fn with_defaults() -> Self {
+        let mut diagrams = Self::new();
+        diagrams.register(CommandBackend::graphviz());
+        diagrams.register(CommandBackend::plantuml());
+        diagrams.register(MermaidBackend);
+        diagrams
+    }
+
+    /// Adds a backend. Earlier backends win when languages overlap.
+    pub fn register(&mut self, backend: impl DiagramBackend + 'static) {
+        self.backends.push(Box::new(backend));
+    }
+
+    /// Renders a fence in `language` to HTML, or returns `None` when no
+    /// installed backend handles it.
+    ///
+    /// Waits for slow backends, up to their timeout.
+    pub fn render(&self, language: &str, source: &str) -> Option<String> {
+        let backend = self.backend_for(language)?;
+        let key = cache_key(language, source);
+        if let Some(html) = self.cached(key) {
+            return Some(html);
+        }
+
+        let html = render_html(backend, language, source);
+        self.store(key, html.clone());
+        Some(html)
+    }
+
+    /// Like [`Diagrams::render`], but a diagram from a slow backend that is
+    /// not cached yet comes back as a placeholder. It is queued for
+    /// [`Diagrams::take_pending`] instead of being waited for.
+    pub fn render_deferred(&self, language: &str, source: &str) -> Option<String> {
+        let backend = self.backend_for(language)?;
+        if !backend.is_slow() {
+            return self.render(language, source);
+        }
+
+        let key = cache_key(language, source);
+        if let Some(html) = self.cached(key) {
+            return Some(html);
+        }
+        if self.queued.lock().ok()?.insert(key) {
+            self.pending
+                .lock()
+                .ok()?
+                .push((key, language.to_string(), source.to_string()));
+        }
+        Some(format!(
+            "<div class=\"diagram diagram-pending\">Rendering {} diagram…</div>\n",
+            escape_html(language)
+        ))
+    }
+
+    /// Takes the diagrams [`Diagrams::render_deferred`] queued. Each job is
+    /// meant to run on another thread; once it is done, rendering again
+    /// picks the diagram up from the cache.
+    pub fn take_pending(self: &Arc<Self>) -> Vec<DiagramJob> {
+        let Ok(mut pending) = self.pending.lock() else {
+            return Vec::new();
+        };
+        pending
+            .drain(..)
+            .map(|(key, language, source)| DiagramJob {
+                diagrams: Arc::clone(self),
+                key,
+                language,
+                source,
+            })
+            .collect()
+    }
+
+    /// Scripts needed to draw fences in the given languages.
+    pub fn scripts_for(&self, languages: &[String]) -> Vec<&str> {
+        self.backends
+            .iter()
+            .filter(|backend| backend.is_available())
+            .filter(|backend| {
+                backend
+                    .languages()
+                    .iter()
+                    .any(|language| languages.iter().any(|used| used == language))
+            })
+            .filter_map(|backend| backend.script())
+            .collect()
+    }
+
+    fn cached(&self, key: u64) -> Option<String> {
+        self.cache.lock().ok()?.get(&key).cloned()
+    }
+
+    fn store(&self, key: u64, html: String) {
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.len() >= CACHE_LIMIT {
+                cache.clear();
+            }
+            cache.insert(key, html);
+        }
+    }
+
+    fn backend_for(&self, language: &str) -> Option<&dyn DiagramBackend> {
+        self.backends
+            .iter()
+            .find(|backend| backend.languages().contains(&language) && backend.is_available())
+            .map(|backend| backend.as_ref())
+    }
+}
+
+impl Default for Diagrams {
+    fn default() -> Self {
+        Self::with_defaults()
+    }
+}
+
+impl fmt::Debug for Diagrams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let languages: Vec<&str> = self
+            .backends
+            .iter()
+            .flat_map(|backend| backend.languages().iter().copied())
+            .collect();
+        f.debug_struct("Diagrams").field("languages", &languages).finish()
+    }
+}
+
+/// A slow diagram to render off the main thread, see
+/// [`Diagrams::take_pending`].
+pub struct DiagramJob {
+    diagrams: Arc<Diagrams>,
+    key: u64,
+    language: String,
+    source: String,
+}
+
+impl DiagramJob {
+    /// Renders the diagram into the cache, blocking until the backend is
+    /// done or times out.
+    pub fn run(self) {
+        let diagrams = &self.diagrams;
+        if let Some(backend) = diagrams.backend_for(&self.language) {
+            let html = render_html(backend, &self.language, &self.source);
+            diagrams.store(self.key, html);
+        }
+        if let Ok(mut queued) = diagrams.queued.lock() {
+            queued.remove(&self.key);
+        }
+    }
+}
+
+impl fmt::Debug for DiagramJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiagramJob").field("language", &self.language).finish()
+    }
+}
+
+fn cache_key(language: &str, source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (language, source).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The diagram wrapped for the page, or the error shown in its place.
+fn render_html(backend: &dyn DiagramBackend, language: &str, source: &str) -> String {
+    match backend.render(source) {
+        Ok(html) => format!("<div class=\"diagram diagram-{}\">{}</div>\n", escape_html(language), html),
+        Err(e) => format!("<pre class=\"diagram-error\">{}</pre>\n", escape_html(&e)),
+    }
+}
+
+/// Full path of `program` if it is on `PATH`.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A slow backend that wraps the source in an `<svg>`.
+    struct Echo;
+
+    impl DiagramBackend for Echo {
+        fn languages(&self) -> &[&str] {
+            &["echo"]
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn render(&self, source: &str) -> Result<String, String> {
+            Ok(format!("<svg>{}</svg>", source))
+        }
+
+        fn is_slow(&self) -> bool {
+            true
+        }
+    }
+
+    fn echo_diagrams() -> Arc<Diagrams> {
+        let mut diagrams = Diagrams::new();
+        diagrams.register(Echo);
+        Arc::new(diagrams)
+    }
+
+    #[test]
+    fn slow_diagrams_render_in_the_background() {
+        let diagrams = echo_diagrams();
+
+        let placeholder = diagrams.render_deferred("echo", "a").unwrap();
+        assert!(placeholder.contains("diagram-pending"));
+        // Still rendering, so it is not queued twice
+        diagrams.render_deferred("echo", "a");
+
+        let jobs = diagrams.take_pending();
+        assert_eq!(jobs.len(), 1);
+        jobs.into_iter().for_each(DiagramJob::run);
+
+        assert_eq!(
+            diagrams.render_deferred("echo", "a").unwrap(),
+            "<div class=\"diagram diagram-echo\"><svg>a</svg></div>\n"
+        );
+        assert!(diagrams.take_pending().is_empty());
+    }
+
+    #[test]
+    fn render_waits_for_slow_diagrams() {
+        let diagrams = echo_diagrams();
+        assert_eq!(
+            diagrams.render("echo", "a").unwrap(),
+            "<div class=\"diagram diagram-echo\"><svg>a</svg></div>\n"
+        );
+        assert!(diagrams.take_pending().is_empty());
+    }
+
+    #[test]
+    fn unknown_languages_are_left_as_code() {
+        let diagrams = echo_diagrams();
+        assert_eq!(diagrams.render("rust", "fn main() {}"), None);
+        assert_eq!(diagrams.render_deferred("rust", "fn main() {}"), None);
+    }
+
+    #[test]
+    fn command_output_is_cut_down_to_the_svg() {
+        // `cat` echoes the source back, standing in for a diagram program
+        let backend = CommandBackend::new(&["cat"], "cat", &[]);
+        assert_eq!(
+            backend.render("<?xml version=\"1.0\"?>\n<svg></svg>"),
+            Ok("<svg></svg>".to_string())
+        );
+        assert!(backend.render("not a diagram").is_err());
+    }
+
+    #[test]
+    fn pages_only_carry_the_scripts_they_use() {
+        let diagrams = Diagrams::with_defaults();
+        assert!(diagrams.scripts_for(&["rust".to_string()]).is_empty());
+        assert_eq!(diagrams.scripts_for(&["mermaid".to_string()]), vec![MERMAID_SCRIPT]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Line diffs, for comparing a document with another version of its text.
+
+/// Largest table the line matcher builds; bigger changes are shown as the
+/// old lines removed and the new lines added.
+const TABLE_LIMIT: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Indices of the lines of `old` that are removed and of the lines of
+/// `new` that are added, for marking changes side by side.
+pub fn changed_lines(old: &str, new: &str) -> (Vec<usize>, Vec<usize>) {
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut old_line, mut new_line) = (0, 0);
+    for line in diff_lines(old, new) {
+        match line {
+            Line::Same(_) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Line::Removed(_) => {
+                removed.push(old_line);
+                old_line += 1;
+            }
+            Line::Added(_) => {
+                added.push(new_line);
+                new_line += 1;
+            }
+        }
+    }
+    (removed, added)
+}
+
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Common ends are matched directly, which keeps the table small for
+    // the usual handful of edits
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    if (old_middle.len() + 1) * (new_middle.len() + 1) > TABLE_LIMIT {
+        lines.extend(old_middle.iter().map(|line| Line::Removed(line)));
+        lines.extend(new_middle.iter().map(|line| Line::Added(line)));
+    } else {
+        lines.extend(matched_lines(old_middle, new_middle));
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| Line::Same(line)));
+    lines
+}
+
+/// Diff through the longest common subsequence of lines.
+fn matched_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lengths[i][j]: common lines between old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    lines.extend(new[j..].iter().map(|line| Line::Added(line)));
+    lines
+}
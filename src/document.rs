@@ -0,0 +1,1903 @@
+use crate::compare;
+use crate::settings::Preferences;
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use glib::subclass::Signal;
+use gtk::{gdk, gio, glib};
+use mymd::files::{self, FileError};
+use mymd::highlight;
+use mymd::metadata::{self, FrontMatter};
+use mymd::positions::{self, Position};
+use mymd::recovery::{self, Snapshot};
+use mymd::render::{Block, Heading, Renderer};
+use sourceview::prelude::*;
+use webkit::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Preview side of the editor/preview bridge, injected into every page.
+const PREVIEW_SCRIPT: &str = include_str!("../data/preview.js");
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ViewMode {
+    #[default]
+    Write,
+    Preview,
+    Split,
+}
+
+impl ViewMode {
+    /// Name used in the settings.
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewMode::Write => "write",
+            ViewMode::Preview => "preview",
+            ViewMode::Split => "split",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "write" => Some(ViewMode::Write),
+            "preview" => Some(ViewMode::Preview),
+            "split" => Some(ViewMode::Split),
+            _ => None,
+        }
+    }
+}
+
+/// Share of the width the editor gets in split view, until it is dragged.
+const DEFAULT_SPLIT_RATIO: f64 = 0.5;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct MyMarkdownDocument {
+        pub source_view: RefCell<Option<sourceview::View>>,
+        pub web_view: RefCell<Option<webkit::WebView>>,
+        pub current_file: RefCell<Option<PathBuf>>,
+        pub view_mode: Cell<ViewMode>,
+        pub paned: RefCell<Option<gtk::Paned>>,
+        /// Editor share of the split view width.
+        pub split_ratio: Cell<f64>,
+        pub editor_frame: RefCell<Option<gtk::Frame>>,
+        pub preview_frame: RefCell<Option<gtk::Frame>>,
+        pub modified: Cell<bool>,
+        pub initial_dir: RefCell<PathBuf>,
+        pub editor_scrolled: RefCell<Option<gtk::ScrolledWindow>>,
+        pub preview_update_pending: Cell<bool>,
+        pub scrolling_from_preview: Cell<bool>,
+        pub preview_ready: Cell<bool>,
+        pub preview_base_uri: RefCell<String>,
+        pub preview_blocks: RefCell<Vec<Block>>,
+        /// Counts buffer edits, so a click can tell whether the preview is current.
+        pub revision: Cell<u64>,
+        /// The revision the preview last rendered.
+        pub preview_revision: Cell<u64>,
+        /// Diagram scripts injected into the loaded page.
+        pub preview_scripts: RefCell<Vec<String>>,
+        pub renderer: RefCell<Renderer>,
+        pub front_matter: RefCell<Option<FrontMatter>>,
+        pub metadata_panel: RefCell<Option<gtk::ListBox>>,
+        pub metadata_expander: RefCell<Option<adw::ExpanderRow>>,
+        pub metadata_rows: RefCell<Vec<adw::EntryRow>>,
+        pub editing_metadata: Cell<bool>,
+        pub split_view: RefCell<Option<adw::OverlaySplitView>>,
+        pub outline_list: RefCell<Option<gtk::ListBox>>,
+        pub outline: RefCell<Vec<Heading>>,
+        pub preferences: RefCell<Preferences>,
+        pub style_handler: RefCell<Option<glib::SignalHandlerId>>,
+        /// Name of the autosaved copy, once one was written.
+        pub recovery_id: RefCell<Option<String>>,
+        pub autosave_source: RefCell<Option<glib::SourceId>>,
+        /// Modification time of the file when it was last read or written.
+        pub disk_modified: Cell<Option<SystemTime>>,
+        pub file_monitor: RefCell<Option<gio::FileMonitor>>,
+        pub disk_banner: RefCell<Option<gtk::Revealer>>,
+        pub disk_banner_label: RefCell<Option<gtk::Label>>,
+        pub compare_btn: RefCell<Option<gtk::Button>>,
+        pub reload_btn: RefCell<Option<gtk::Button>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for MyMarkdownDocument {
+        const NAME: &'static str = "MyMarkdownDocument";
+        type Type = super::MyMarkdownDocument;
+        type ParentType = gtk::Box;
+    }
+
+    impl ObjectImpl for MyMarkdownDocument {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // Emitted when the title, modified state or view mode changes
+            SIGNALS.get_or_init(|| vec![Signal::builder("changed").build()])
+        }
+
+        fn dispose(&self) {
+            // The style manager outlives closed tabs
+            if let Some(handler) = self.style_handler.take() {
+                adw::StyleManager::default().disconnect(handler);
+            }
+            if let Some(monitor) = self.file_monitor.take() {
+                monitor.cancel();
+            }
+        }
+    }
+
+    impl WidgetImpl for MyMarkdownDocument {}
+    impl BoxImpl for MyMarkdownDocument {}
+}
+
+glib::wrapper! {
+    pub struct MyMarkdownDocument(ObjectSubclass<imp::MyMarkdownDocument>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Orientable;
+}
+
+impl MyMarkdownDocument {
+    pub fn new(initial_dir: PathBuf) -> Self {
+        let document: Self = glib::Object::builder()
+            .property("orientation", gtk::Orientation::Vertical)
+            .build();
+
+        // Store initial directory for the save dialog
+        document.imp().initial_dir.replace(initial_dir);
+        // Typing must not wait for Graphviz or PlantUML. Page scripts are
+        // disabled in the preview, so diagram scripts are injected instead
+        // of inlined, see `load_preview_content`.
+        document.imp().renderer.replace(
+            Renderer::new()
+                .with_deferred_diagrams(true)
+                .with_inline_scripts(false),
+        );
+
+        document.setup_ui();
+        document.set_view_mode(ViewMode::Write);
+        document
+    }
+
+    /// Creates a document from an autosaved copy, for the file it belongs to
+    /// if it has one.
+    pub fn for_snapshot(snapshot: Snapshot, initial_dir: PathBuf) -> Self {
+        let document = Self::new(initial_dir);
+        if let Some(ref path) = snapshot.path {
+            // The copy replaces the text anyway, so a file that is gone or
+            // unreadable still gives the document its name
+            if document.load_file(path).is_err() {
+                document.set_file(Some(path.clone()));
+            }
+        }
+        document.restore_snapshot(snapshot);
+        document
+    }
+
+    /// Creates a document for a file given on the command line or by the launcher.
+    pub fn for_path(path: PathBuf, initial_dir: PathBuf) -> Result<Self, FileError> {
+        let document = Self::new(initial_dir);
+        document.handle_file_arg(path)?;
+        Ok(document)
+    }
+
+    fn setup_ui(&self) {
+        let imp = self.imp();
+
+        // Create paned view for editor and preview
+        let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+        paned.set_vexpand(true);
+        paned.set_hexpand(true);
+        paned.set_shrink_start_child(false);
+        paned.set_shrink_end_child(false);
+        paned.set_resize_start_child(true);
+        paned.set_resize_end_child(true);
+
+        // Editor side
+        let editor_frame = self.create_editor();
+        paned.set_start_child(Some(&editor_frame));
+
+        // Preview side
+        let preview_frame = self.create_preview();
+        paned.set_end_child(Some(&preview_frame));
+
+        // Remember where the divider is dragged to, as a share of the width
+        imp.split_ratio.set(DEFAULT_SPLIT_RATIO);
+        let document = self.downgrade();
+        paned.connect_position_notify(move |paned| {
+            let Some(document) = document.upgrade() else {
+                return;
+            };
+            let width = paned.width();
+            if document.view_mode() == ViewMode::Split && paned.is_mapped() && width > 0 {
+                let ratio = paned.position() as f64 / width as f64;
+                document.imp().split_ratio.set(ratio.clamp(0.1, 0.9));
+            }
+        });
+
+        imp.paned.replace(Some(paned.clone()));
+        imp.editor_frame.replace(Some(editor_frame));
+        imp.preview_frame.replace(Some(preview_frame));
+
+        // Outline sidebar next to the editor and preview
+        let split_view = adw::OverlaySplitView::new();
+        split_view.set_vexpand(true);
+        split_view.set_sidebar(Some(&self.create_outline()));
+        split_view.set_content(Some(&paned));
+        split_view.set_show_sidebar(false);
+        imp.split_view.replace(Some(split_view.clone()));
+
+        let disk_banner = self.create_disk_banner();
+        self.append(&disk_banner);
+        let metadata_panel = self.create_metadata_panel();
+        self.append(&metadata_panel);
+        self.append(&split_view);
+    }
+
+    fn create_outline(&self) -> gtk::ScrolledWindow {
+        let imp = self.imp();
+
+        let list = gtk::ListBox::new();
+        list.add_css_class("navigation-sidebar");
+        list.set_placeholder(Some(&gtk::Label::new(Some("No Headings"))));
+
+        // Jump to the heading in both the editor and the preview
+        let document = self.clone();
+        list.connect_row_activated(move |_, row| {
+            let heading = document.imp().outline.borrow().get(row.index() as usize).cloned();
+            if let Some(heading) = heading {
+                document.goto_heading(&heading);
+            }
+        });
+
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_hscrollbar_policy(gtk::PolicyType::Never);
+        scrolled.set_child(Some(&list));
+
+        imp.outline_list.replace(Some(list));
+        scrolled
+    }
+
+    /// Bar offering to reload the file when another program changes it while
+    /// there are unsaved edits.
+    fn create_disk_banner(&self) -> gtk::Revealer {
+        let imp = self.imp();
+
+        let label = gtk::Label::new(None);
+        label.set_wrap(true);
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+
+        let compare_btn = gtk::Button::with_label("Compare");
+        let keep_btn = gtk::Button::with_label("Keep Mine");
+        let reload_btn = gtk::Button::with_label("Reload");
+        // Reloading throws the unsaved edits away
+        reload_btn.add_css_class("destructive-action");
+
+        let document = self.clone();
+        compare_btn.connect_clicked(move |_| document.show_disk_changes());
+        let document = self.clone();
+        keep_btn.connect_clicked(move |_| document.keep_mine());
+        let document = self.clone();
+        reload_btn.connect_clicked(move |_| document.reload());
+
+        let bar = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        bar.add_css_class("toolbar");
+        bar.set_margin_start(6);
+        bar.set_margin_end(6);
+        bar.append(&label);
+        bar.append(&compare_btn);
+        bar.append(&keep_btn);
+        bar.append(&reload_btn);
+
+        let revealer = gtk::Revealer::new();
+        revealer.set_child(Some(&bar));
+
+        imp.disk_banner.replace(Some(revealer.clone()));
+        imp.disk_banner_label.replace(Some(label));
+        imp.compare_btn.replace(Some(compare_btn));
+        imp.reload_btn.replace(Some(reload_btn));
+        revealer
+    }
+
+    /// Collapsible form for the document's front matter, hidden when the
+    /// document has none.
+    fn create_metadata_panel(&self) -> gtk::ListBox {
+        let imp = self.imp();
+
+        let panel = gtk::ListBox::new();
+        panel.add_css_class("boxed-list");
+        panel.set_selection_mode(gtk::SelectionMode::None);
+        panel.set_margin_top(6);
+        panel.set_margin_bottom(6);
+        panel.set_margin_start(12);
+        panel.set_margin_end(12);
+        panel.set_visible(false);
+
+        let expander = adw::ExpanderRow::builder()
+            .title("Front Matter")
+            .build();
+        panel.append(&expander);
+
+        imp.metadata_panel.replace(Some(panel.clone()));
+        imp.metadata_expander.replace(Some(expander));
+        panel
+    }
+
+    fn create_editor(&self) -> gtk::Frame {
+        let imp = self.imp();
+
+        let frame = gtk::Frame::new(None);
+        frame.add_css_class("view");
+
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_vexpand(true);
+        scrolled.set_hexpand(true);
+
+        // Create source view with markdown language
+        let buffer = sourceview::Buffer::new(None);
+
+        // Set markdown language for syntax highlighting
+        let lang_manager = sourceview::LanguageManager::default();
+        if let Some(lang) = lang_manager.language("markdown") {
+            buffer.set_language(Some(&lang));
+        }
+
+        let source_view = sourceview::View::with_buffer(&buffer);
+        source_view.set_monospace(true);
+        source_view.set_auto_indent(true);
+        source_view.set_smart_backspace(true);
+
+        // Setup paste handler for plain text
+        self.setup_paste_handler(&source_view);
+
+        // Connect buffer changed signal for live preview and modified tracking
+        let document = self.clone();
+        buffer.connect_changed(move |_| {
+            let imp = document.imp();
+            imp.revision.set(imp.revision.get() + 1);
+            document.set_modified(true);
+            document.update_metadata();
+            document.schedule_preview_update();
+            document.schedule_autosave();
+        });
+
+        // Connect scroll event for sync in split mode
+        let vadjustment = scrolled.vadjustment();
+        let document = self.clone();
+        vadjustment.connect_value_changed(move |_| {
+            let imp = document.imp();
+            if imp.view_mode.get() == ViewMode::Split && !imp.scrolling_from_preview.get() {
+                document.sync_preview_to_editor();
+            }
+        });
+
+        scrolled.set_child(Some(&source_view));
+        frame.set_child(Some(&scrolled));
+
+        imp.source_view.replace(Some(source_view));
+        imp.editor_scrolled.replace(Some(scrolled));
+        self.configure_editor();
+
+        // Follow the system light/dark style, before the preview first loads
+        self.apply_style();
+        let document = self.downgrade();
+        let handler = adw::StyleManager::default().connect_dark_notify(move |_| {
+            if let Some(document) = document.upgrade() {
+                document.apply_style();
+            }
+        });
+        imp.style_handler.replace(Some(handler));
+
+        frame
+    }
+
+    /// Matches the editor scheme, the code theme and the preview stylesheet
+    /// to the system light/dark style and the chosen preview theme.
+    fn apply_style(&self) {
+        let imp = self.imp();
+        let dark = adw::StyleManager::default().is_dark();
+
+        let buffer = imp
+            .source_view
+            .borrow()
+            .as_ref()
+            .and_then(|source_view| source_view.buffer().downcast::<sourceview::Buffer>().ok());
+        let preferences = imp.preferences.borrow().clone();
+        let scheme = style_scheme_for(preferences.style_scheme.as_deref(), dark);
+
+        let mut renderer = imp.renderer.take();
+        if let (Some(buffer), Some(scheme)) = (buffer, scheme) {
+            buffer.set_style_scheme(Some(&scheme));
+            // Color code blocks in the preview to match
+            renderer = renderer.with_code_theme(highlight::theme_for_scheme(&scheme.id()));
+        }
+        renderer = renderer.with_stylesheet(preferences.preview_theme.stylesheet(dark));
+        let stylesheet = renderer.page_stylesheet();
+        imp.renderer.replace(renderer);
+
+        // Restyle the loaded page in place; a load in flight needs redoing
+        let Some(web_view) = imp.web_view.borrow().clone() else {
+            return;
+        };
+        if imp.preview_ready.get() {
+            let js = format!(
+                "if (window.mymd) window.mymd.setStylesheet({});",
+                js_string(&stylesheet)
+            );
+            web_view.evaluate_javascript(
+                &js,
+                None,
+                None,
+                None::<&gio::Cancellable>,
+                |_| {},
+            );
+        } else {
+            self.load_preview_content(&web_view, &self.text());
+        }
+    }
+
+    /// Applies changed preferences to the editor and the preview.
+    pub fn apply_preferences(&self, preferences: Preferences) {
+        let imp = self.imp();
+        let previous = imp.preferences.replace(preferences.clone());
+        if previous == preferences {
+            return;
+        }
+
+        self.configure_editor();
+        if previous.style_scheme != preferences.style_scheme
+            || previous.preview_theme != preferences.preview_theme
+        {
+            self.apply_style();
+        }
+    }
+
+    /// Sets the editor options that come from the preferences.
+    fn configure_editor(&self) {
+        let imp = self.imp();
+        let Some(ref source_view) = *imp.source_view.borrow() else {
+            return;
+        };
+        let preferences = imp.preferences.borrow();
+
+        source_view.set_tab_width(preferences.tab_width);
+        source_view.set_indent_width(preferences.tab_width as i32);
+        source_view.set_insert_spaces_instead_of_tabs(preferences.insert_spaces);
+        source_view.set_wrap_mode(if preferences.wrap_lines {
+            gtk::WrapMode::Word
+        } else {
+            gtk::WrapMode::None
+        });
+        source_view.set_show_line_numbers(preferences.show_line_numbers);
+        source_view.set_highlight_current_line(preferences.highlight_current_line);
+
+        let margin = preferences.editor_margin as i32;
+        source_view.set_left_margin(margin);
+        source_view.set_right_margin(margin);
+        source_view.set_top_margin(margin);
+        source_view.set_bottom_margin(margin);
+    }
+
+    fn create_preview(&self) -> gtk::Frame {
+        let imp = self.imp();
+
+        let frame = gtk::Frame::new(None);
+        frame.add_css_class("view");
+
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_vexpand(true);
+        scrolled.set_hexpand(true);
+
+        // The bridge script, added on each load, reports preview scrolling
+        // back through the "mymd" message handler
+        let content_manager = webkit::UserContentManager::new();
+        content_manager.register_script_message_handler("mymd", None);
+        let document = self.clone();
+        content_manager.connect_script_message_received(Some("mymd"), move |_, value| {
+            document.handle_preview_message(&value.to_str());
+        });
+
+        // Create webkit view for preview
+        let web_view = webkit::WebView::builder()
+            .user_content_manager(&content_manager)
+            .build();
+        web_view.set_vexpand(true);
+        web_view.set_hexpand(true);
+
+        // Configure WebView settings (enable JS for scroll sync)
+        if let Some(settings) = webkit::prelude::WebViewExt::settings(&web_view) {
+            settings.set_enable_write_console_messages_to_stdout(false);
+            settings.set_enable_developer_extras(false);
+            settings.set_enable_javascript(true);  // Needed for scroll sync
+            settings.set_enable_javascript_markup(false);
+            // Relative images next to the document load through its file://
+            // base URI, but local pages never get access to other origins
+            settings.set_allow_file_access_from_file_urls(true);
+            settings.set_allow_universal_access_from_file_urls(false);
+        }
+
+        // Keep link clicks from navigating the preview away from the document
+        let document = self.clone();
+        web_view.connect_decide_policy(move |_, decision, decision_type| {
+            document.decide_preview_policy(decision, decision_type)
+        });
+
+        // Later updates are patched into the loaded page, so only act once
+        // a full load has finished
+        let document = self.clone();
+        web_view.connect_load_changed(move |_, event| {
+            if event == webkit::LoadEvent::Finished {
+                document.imp().preview_ready.set(true);
+                document.update_preview();
+            }
+        });
+
+        // Load initial empty content
+        self.load_preview_content(&web_view, "");
+
+        scrolled.set_child(Some(&web_view));
+        frame.set_child(Some(&scrolled));
+
+        imp.web_view.replace(Some(web_view));
+        frame
+    }
+
+    /// Routes link clicks in the preview.
+    ///
+    /// Web links open in the browser, links to markdown files open in the
+    /// editor and `#anchors` scroll the preview. Returns `true` when the
+    /// decision was handled here.
+    fn decide_preview_policy(
+        &self,
+        decision: &webkit::PolicyDecision,
+        decision_type: webkit::PolicyDecisionType,
+    ) -> bool {
+        if decision_type != webkit::PolicyDecisionType::NavigationAction
+            && decision_type != webkit::PolicyDecisionType::NewWindowAction
+        {
+            return false;
+        }
+
+        let Some(navigation) = decision.downcast_ref::<webkit::NavigationPolicyDecision>() else {
+            return false;
+        };
+        let Some(mut action) = navigation.navigation_action() else {
+            return false;
+        };
+
+        // Let our own load_html calls through
+        if action.navigation_type() != webkit::NavigationType::LinkClicked {
+            return false;
+        }
+
+        let Some(uri) = action.request().and_then(|request| request.uri()) else {
+            return false;
+        };
+        decision.ignore();
+
+        let base_uri = self.base_uri();
+        if let Some(anchor) = uri.strip_prefix(base_uri.as_str()).and_then(|rest| rest.strip_prefix('#')) {
+            self.scroll_preview_to_anchor(anchor);
+            return true;
+        }
+
+        let parent = self.root().and_downcast::<gtk::Window>();
+        let scheme = glib::Uri::peek_scheme(&uri).map(|s| s.to_string()).unwrap_or_default();
+
+        if scheme == "file" {
+            // Links to other notes open in the editor, anything else
+            // (images, PDFs, ...) in its default application
+            let target = uri.split('#').next().unwrap_or_default();
+            let path = gio::File::for_uri(target).path();
+            let is_markdown = path.as_ref().is_some_and(|path| {
+                path.extension().is_some_and(|ext| ext == "md" || ext == "markdown")
+            });
+
+            if let (true, Some(path)) = (is_markdown, path) {
+                if let Some(window) = parent.and_downcast::<crate::window::MyMarkdownWindow>() {
+                    window.open_file(&path);
+                }
+                return true;
+            }
+        } else if !matches!(scheme.as_str(), "http" | "https" | "mailto") {
+            return true;
+        }
+
+        gtk::UriLauncher::new(&uri).launch(parent.as_ref(), None::<&gio::Cancellable>, |result| {
+            if let Err(e) = result {
+                eprintln!("Error opening link: {}", e);
+            }
+        });
+        true
+    }
+
+    /// Scrolls the preview so the element with the given id is at the top.
+    pub fn scroll_preview_to_anchor(&self, anchor: &str) {
+        if let Some(ref web_view) = *self.imp().web_view.borrow() {
+            let js = format!(
+                "(function() {{ var el = document.getElementById(decodeURIComponent({})); if (el) el.scrollIntoView(); }})();",
+                js_string(anchor)
+            );
+            web_view.evaluate_javascript(
+                &js,
+                None,
+                None,
+                None::<&gio::Cancellable>,
+                |_| {},
+            );
+        }
+    }
+
+    fn setup_paste_handler(&self, source_view: &sourceview::View) {
+        // Override paste to always use plain text
+        let controller = gtk::EventControllerKey::new();
+        let view = source_view.clone();
+
+        controller.connect_key_pressed(move |_, key, _, modifier| {
+            // Check for Ctrl+V
+            if modifier.contains(gdk::ModifierType::CONTROL_MASK)
+                && (key == gdk::Key::v || key == gdk::Key::V)
+            {
+                let clipboard = view.clipboard();
+                let view_clone = view.clone();
+
+                // Read plain text from clipboard
+                clipboard.read_text_async(None::<&gio::Cancellable>, move |result| {
+                    if let Ok(Some(text)) = result {
+                        let buffer = view_clone.buffer();
+                        buffer.delete_selection(true, true);
+                        buffer.insert_at_cursor(&text);
+                    }
+                });
+
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
+
+        source_view.add_controller(controller);
+    }
+
+    /// Name shown in the tab and the window title: the front matter title,
+    /// or else the file name.
+    pub fn title(&self) -> String {
+        if let Some(title) = self.imp().front_matter.borrow().as_ref().and_then(FrontMatter::title) {
+            return title.to_string();
+        }
+
+        match *self.imp().current_file.borrow() {
+            Some(ref path) => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            None => "Untitled".to_string(),
+        }
+    }
+
+    pub fn file(&self) -> Option<PathBuf> {
+        self.imp().current_file.borrow().clone()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.imp().modified.get()
+    }
+
+    /// True for an untitled document that was never typed into.
+    pub fn is_pristine(&self) -> bool {
+        self.imp().current_file.borrow().is_none() && !self.is_modified() && self.text().is_empty()
+    }
+
+    fn set_modified(&self, modified: bool) {
+        if self.imp().modified.replace(modified) != modified {
+            self.emit_changed();
+        }
+    }
+
+    fn set_file(&self, path: Option<PathBuf>) {
+        self.imp().current_file.replace(path);
+        self.watch_file();
+        self.emit_changed();
+    }
+
+    fn emit_changed(&self) {
+        self.emit_by_name::<()>("changed", &[]);
+    }
+
+    pub fn connect_changed<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_local("changed", false, move |values| {
+            let document = values[0].get::<Self>().unwrap();
+            f(&document);
+            None
+        })
+    }
+
+    pub fn text(&self) -> String {
+        match *self.imp().source_view.borrow() {
+            Some(ref source_view) => {
+                let buffer = source_view.buffer();
+                let start = buffer.start_iter();
+                let end = buffer.end_iter();
+                buffer.text(&start, &end, false).to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Moves keyboard focus into the editor.
+    pub fn focus_editor(&self) {
+        if let Some(ref source_view) = *self.imp().source_view.borrow() {
+            source_view.grab_focus();
+        }
+    }
+
+    pub fn view_mode(&self) -> ViewMode {
+        self.imp().view_mode.get()
+    }
+
+    pub fn set_view_mode(&self, mode: ViewMode) {
+        let imp = self.imp();
+        if imp.view_mode.replace(mode) != mode {
+            self.emit_changed();
+        }
+
+        if let (Some(editor_frame), Some(preview_frame), Some(paned)) = (
+            &*imp.editor_frame.borrow(),
+            &*imp.preview_frame.borrow(),
+            &*imp.paned.borrow(),
+        ) {
+            match mode {
+                ViewMode::Write => {
+                    editor_frame.set_visible(true);
+                    preview_frame.set_visible(false);
+                }
+                ViewMode::Preview => {
+                    editor_frame.set_visible(false);
+                    preview_frame.set_visible(true);
+                    // The page keeps its own scroll position while hidden
+                    self.update_preview();
+                }
+                ViewMode::Split => {
+                    editor_frame.set_visible(true);
+                    preview_frame.set_visible(true);
+                    // Set position once the paned has a width, which a tab
+                    // opened in the background only gets when it is shown
+                    let ratio = imp.split_ratio.get();
+                    paned.add_tick_callback(move |paned, _| {
+                        let width = paned.width();
+                        if width == 0 {
+                            return glib::ControlFlow::Continue;
+                        }
+                        paned.set_position((width as f64 * ratio).round() as i32);
+                        glib::ControlFlow::Break
+                    });
+                    self.update_preview();
+                }
+            }
+        }
+    }
+
+    pub fn split_ratio(&self) -> f64 {
+        self.imp().split_ratio.get()
+    }
+
+    /// Sets the editor share of the split view width, applied the next time
+    /// split view is shown.
+    pub fn set_split_ratio(&self, ratio: f64) {
+        self.imp().split_ratio.set(ratio.clamp(0.1, 0.9));
+    }
+
+    /// Remembers the cursor and scroll position of the open file.
+    pub fn save_position(&self) {
+        let imp = self.imp();
+        let (Some(path), Some(source_view)) =
+            (self.file(), imp.source_view.borrow().clone())
+        else {
+            return;
+        };
+
+        let buffer = source_view.buffer();
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        let top_line = self.editor_top_line().unwrap_or(1.0);
+        positions::save(
+            &path,
+            Position {
+                line: cursor.line() as u32,
+                column: cursor.line_offset() as u32,
+                top_line: (top_line as u32).saturating_sub(1),
+            },
+        );
+    }
+
+    /// Puts the cursor and the scroll position back where `path` was left.
+    fn restore_position(&self, path: &Path) {
+        let Some(position) = positions::load(path) else {
+            return;
+        };
+        let Some(source_view) = self.imp().source_view.borrow().clone() else {
+            return;
+        };
+
+        let buffer = source_view.buffer();
+        let cursor = buffer
+            .iter_at_line_offset(position.line as i32, position.column as i32)
+            .or_else(|| buffer.iter_at_line(position.line as i32))
+            .unwrap_or_else(|| buffer.end_iter());
+        buffer.place_cursor(&cursor);
+
+        // Scrolling to a mark waits for the lines to be laid out
+        let top = buffer
+            .iter_at_line(position.top_line as i32)
+            .unwrap_or_else(|| buffer.end_iter());
+        let mark = match buffer.mark("mymd-restore-top") {
+            Some(mark) => {
+                buffer.move_mark(&mark, &top);
+                mark
+            }
+            None => buffer.create_mark(Some("mymd-restore-top"), &top, true),
+        };
+        source_view.scroll_to_mark(&mark, 0.0, true, 0.0, 0.0);
+    }
+
+    /// Scrolls the preview so the block for the editor's top line is at the top.
+    fn sync_preview_to_editor(&self) {
+        let Some(line) = self.editor_top_line() else {
+            return;
+        };
+
+        if let Some(ref web_view) = *self.imp().web_view.borrow() {
+            let js = format!("if (window.mymd) window.mymd.scrollToLine({});", line);
+            web_view.evaluate_javascript(
+                &js,
+                None,
+                None,
+                None::<&gio::Cancellable>,
+                |_| {},
+            );
+        }
+    }
+
+    /// Fractional 1-based source line at the top of the editor viewport.
+    fn editor_top_line(&self) -> Option<f64> {
+        let imp = self.imp();
+        let source_view = imp.source_view.borrow().clone()?;
+        let scrolled = imp.editor_scrolled.borrow().clone()?;
+
+        let y = scrolled.vadjustment().value() as i32;
+        let (iter, line_top) = source_view.line_at_y(y);
+        let (_, height) = source_view.line_yrange(&iter);
+        let fraction = if height > 0 {
+            ((y - line_top) as f64 / height as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Some(iter.line() as f64 + 1.0 + fraction)
+    }
+
+    /// Scrolls the editor so the given fractional 1-based line is at the top.
+    fn scroll_editor_to_line(&self, line: f64) {
+        let imp = self.imp();
+        let (Some(source_view), Some(scrolled)) = (
+            imp.source_view.borrow().clone(),
+            imp.editor_scrolled.borrow().clone(),
+        ) else {
+            return;
+        };
+
+        let Some(iter) = source_view.buffer().iter_at_line((line.floor() as i32 - 1).max(0)) else {
+            return;
+        };
+        let (y, height) = source_view.line_yrange(&iter);
+
+        imp.scrolling_from_preview.set(true);
+        scrolled.vadjustment().set_value(y as f64 + line.fract() * height as f64);
+        imp.scrolling_from_preview.set(false);
+    }
+
+    /// Handles a message posted by the preview bridge script.
+    fn handle_preview_message(&self, message: &str) {
+        let Some((name, argument)) = message.split_once(':') else {
+            return;
+        };
+
+        match name {
+            // Scrolling the preview drives the editor in split view
+            "scroll" => {
+                if self.imp().view_mode.get() == ViewMode::Split
+                    && let Ok(line) = argument.parse::<f64>()
+                {
+                    self.scroll_editor_to_line(line);
+                }
+            }
+            // Double-click: "goto:LINE:WORD" with the block's line and the
+            // word the click selected
+            "goto" => {
+                let (line, word) = argument.split_once(':').unwrap_or((argument, ""));
+                if let Ok(line) = line.parse::<usize>() {
+                    self.goto_source(line, word);
+                }
+            }
+            // Checkbox toggled: "task:INDEX:CHECKED" with the checkbox's
+            // position among all task checkboxes on the page
+            "task" => {
+                let (index, checked) = argument.split_once(':').unwrap_or((argument, ""));
+                if let Ok(index) = index.parse::<usize>() {
+                    self.set_task_checked(index, checked == "1");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Places the editor cursor on `word` within the block starting at
+    /// `line`, or at the start of that line, and brings the editor into view.
+    fn goto_source(&self, line: usize, word: &str) {
+        let imp = self.imp();
+        let Some(source_view) = imp.source_view.borrow().clone() else {
+            return;
+        };
+        let buffer = source_view.buffer();
+        let Some(start) = buffer.iter_at_line(line as i32 - 1) else {
+            return;
+        };
+
+        // Search up to the next block so a common word lands in the right place
+        let limit = imp
+            .preview_blocks
+            .borrow()
+            .iter()
+            .find(|block| block.line > line)
+            .and_then(|block| buffer.iter_at_line(block.line as i32 - 1));
+        let target = if word.is_empty() {
+            None
+        } else {
+            start
+                .forward_search(word, gtk::TextSearchFlags::TEXT_ONLY, limit.as_ref())
+                .map(|(match_start, _)| match_start)
+        };
+
+        if imp.view_mode.get() == ViewMode::Preview {
+            self.set_view_mode(ViewMode::Split);
+        }
+
+        buffer.place_cursor(&target.unwrap_or(start));
+        source_view.scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.3);
+        source_view.grab_focus();
+    }
+
+    /// Rewrites the marker of the `index`th task list item to `[x]` or `[ ]`
+    /// as a single undoable edit.
+    fn set_task_checked(&self, index: usize, checked: bool) {
+        let imp = self.imp();
+        let Some(source_view) = imp.source_view.borrow().clone() else {
+            return;
+        };
+
+        // The click refers to the page as last rendered; if the buffer has
+        // moved on since, the index may point at another item
+        if imp.preview_revision.get() != imp.revision.get() {
+            self.update_preview();
+            return;
+        }
+
+        let text = self.text();
+        let Some(marker) = imp.renderer.borrow().task_markers(&text).get(index).cloned() else {
+            return;
+        };
+        let mark = if checked { "x" } else { " " };
+        if text[marker.clone()].eq_ignore_ascii_case(&format!("[{}]", mark)) {
+            return;
+        }
+
+        // Replace only the character between the brackets
+        let offset = text[..marker.start + 1].chars().count() as i32;
+        let buffer = source_view.buffer();
+        let mut start = buffer.iter_at_offset(offset);
+        let mut end = buffer.iter_at_offset(offset + 1);
+
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, mark);
+        buffer.end_user_action();
+    }
+
+    pub fn is_outline_visible(&self) -> bool {
+        self.imp()
+            .split_view
+            .borrow()
+            .as_ref()
+            .is_some_and(|split_view| split_view.shows_sidebar())
+    }
+
+    pub fn set_outline_visible(&self, visible: bool) {
+        if let Some(ref split_view) = *self.imp().split_view.borrow() {
+            split_view.set_show_sidebar(visible);
+        }
+        // The outline is only kept current while it is shown
+        self.update_outline();
+    }
+
+    /// Rebuilds the outline sidebar if the headings changed.
+    fn update_outline(&self) {
+        let imp = self.imp();
+        if !self.is_outline_visible() {
+            return;
+        }
+
+        let outline = imp.renderer.borrow().outline(&self.text());
+        if *imp.outline.borrow() == outline {
+            return;
+        }
+
+        if let Some(ref list) = *imp.outline_list.borrow() {
+            list.remove_all();
+            for heading in &outline {
+                let label = gtk::Label::new(Some(&heading.text));
+                label.set_xalign(0.0);
+                label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+                label.set_tooltip_text(Some(&heading.text));
+                label.set_margin_start(12 * (heading.level as i32 - 1));
+                if heading.level == 1 {
+                    label.add_css_class("heading");
+                }
+                list.append(&label);
+            }
+        }
+        imp.outline.replace(outline);
+    }
+
+    /// Scrolls the editor and the preview to `heading`.
+    fn goto_heading(&self, heading: &Heading) {
+        let imp = self.imp();
+
+        if let Some(source_view) = imp.source_view.borrow().clone() {
+            let buffer = source_view.buffer();
+            if let Some(iter) = buffer.iter_at_line(heading.line as i32 - 1) {
+                buffer.place_cursor(&iter);
+                source_view.scroll_to_mark(&buffer.get_insert(), 0.0, true, 0.0, 0.0);
+            }
+            if imp.view_mode.get() != ViewMode::Preview {
+                source_view.grab_focus();
+            }
+        }
+
+        self.scroll_preview_to_anchor(&heading.id);
+    }
+
+    /// Re-reads the front matter after an edit, updating the title and the
+    /// metadata panel when they change.
+    fn update_metadata(&self) {
+        let imp = self.imp();
+        if imp.editing_metadata.get() {
+            return;
+        }
+
+        // Front matter has to open the document, so most edits skip parsing
+        let starts_with_block = match *imp.source_view.borrow() {
+            Some(ref source_view) => {
+                let buffer = source_view.buffer();
+                let start = buffer.start_iter();
+                let mut end = start;
+                end.forward_chars(3);
+                matches!(buffer.text(&start, &end, false).as_str(), "---" | "+++")
+            }
+            None => false,
+        };
+        let front_matter = if starts_with_block {
+            FrontMatter::parse(&self.text())
+        } else {
+            None
+        };
+
+        let old = imp.front_matter.replace(front_matter.clone());
+        if old == front_matter {
+            return;
+        }
+
+        let title = |front_matter: &Option<FrontMatter>| {
+            front_matter.as_ref().and_then(FrontMatter::title).map(str::to_string)
+        };
+        if title(&old) != title(&front_matter) {
+            self.emit_changed();
+        }
+
+        // Moving values do not need new rows, and rebuilding them would
+        // interrupt typing in the form
+        let values = |front_matter: &Option<FrontMatter>| -> Vec<(String, String)> {
+            front_matter
+                .iter()
+                .flat_map(|front_matter| &front_matter.fields)
+                .map(|field| (field.key.clone(), field.value.clone()))
+                .collect()
+        };
+        let format = |front_matter: &Option<FrontMatter>| front_matter.as_ref().map(|f| f.format);
+        if values(&old) != values(&front_matter) || format(&old) != format(&front_matter) {
+            self.rebuild_metadata_rows(front_matter.as_ref());
+        }
+    }
+
+    fn rebuild_metadata_rows(&self, front_matter: Option<&FrontMatter>) {
+        let imp = self.imp();
+        let (Some(panel), Some(expander)) = (
+            imp.metadata_panel.borrow().clone(),
+            imp.metadata_expander.borrow().clone(),
+        ) else {
+            return;
+        };
+
+        for row in imp.metadata_rows.take() {
+            expander.remove(&row);
+        }
+
+        panel.set_visible(front_matter.is_some());
+        let Some(front_matter) = front_matter else {
+            return;
+        };
+
+        expander.set_subtitle(match front_matter.format {
+            metadata::Format::Yaml => "YAML",
+            metadata::Format::Toml => "TOML",
+        });
+
+        let mut rows = Vec::new();
+        for field in &front_matter.fields {
+            let row = adw::EntryRow::builder()
+                .title(field.key.as_str())
+                .text(field.value.as_str())
+                .show_apply_button(true)
+                .build();
+
+            let document = self.clone();
+            let key = field.key.clone();
+            row.connect_apply(move |row| {
+                document.set_metadata_value(&key, &row.text());
+            });
+
+            expander.add_row(&row);
+            rows.push(row);
+        }
+        imp.metadata_rows.replace(rows);
+    }
+
+    /// Writes a front matter value back into the source as one undoable edit.
+    fn set_metadata_value(&self, key: &str, value: &str) {
+        let imp = self.imp();
+        let Some(source_view) = imp.source_view.borrow().clone() else {
+            return;
+        };
+
+        let text = self.text();
+        let Some(front_matter) = FrontMatter::parse(&text) else {
+            return;
+        };
+        let Some(field) = front_matter.fields.iter().find(|field| field.key == key) else {
+            return;
+        };
+        if field.value == value {
+            return;
+        }
+
+        let original = &text[field.value_range.clone()];
+        let replacement = metadata::format_value(front_matter.format, original, value);
+
+        let buffer = source_view.buffer();
+        let start_offset = text[..field.value_range.start].chars().count() as i32;
+        let mut start = buffer.iter_at_offset(start_offset);
+        let mut end = buffer.iter_at_offset(start_offset + original.chars().count() as i32);
+
+        // The half-done edit in between is not worth parsing
+        imp.editing_metadata.set(true);
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &replacement);
+        buffer.end_user_action();
+        imp.editing_metadata.set(false);
+        self.update_metadata();
+    }
+
+    fn handle_file_arg(&self, path: PathBuf) -> Result<(), FileError> {
+        // `mymd notes` creates notes.md, but existing files open as they are
+        let path = if path.exists() || path.extension().is_some_and(|ext| ext == "md") {
+            path
+        } else {
+            let mut name = path.into_os_string();
+            name.push(".md");
+            PathBuf::from(name)
+        };
+
+        if path.exists() {
+            // Open existing file
+            self.load_file(&path)
+        } else {
+            // Create new file
+            self.set_file(Some(path));
+            Ok(())
+        }
+    }
+
+    pub fn load_file(&self, path: &Path) -> Result<(), FileError> {
+        let content = files::read(path)?;
+        if let Some(ref source_view) = *self.imp().source_view.borrow() {
+            let buffer = source_view.buffer();
+            buffer.set_text(&content);
+        }
+        self.set_file(Some(path.to_path_buf()));
+        self.remember_disk_state(path);
+        // Reset modified after loading
+        self.set_modified(false);
+        self.restore_position(path);
+        add_to_recent(path);
+        Ok(())
+    }
+
+    pub fn save_file(&self) {
+        self.save_then(|_| {});
+    }
+
+    /// Saves the document, then calls `on_done` with `true` once it is on
+    /// disk or `false` if the user gave up on saving.
+    ///
+    /// Untitled documents go through the Save As dialog first, and a failed
+    /// write offers to retry or save elsewhere. Whatever the user picks,
+    /// `on_done` is called exactly once, so an action waiting on the save
+    /// either goes ahead or is abandoned.
+    pub fn save_then<F>(&self, on_done: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        let path = self.imp().current_file.borrow().clone();
+        match path {
+            Some(path) => self.write_then(path, on_done),
+            None => self.save_file_as_then(on_done),
+        }
+    }
+
+    pub fn save_file_as(&self) {
+        self.save_file_as_then(|_| {});
+    }
+
+    fn save_file_as_then<F>(&self, on_done: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        let dialog = gtk::FileDialog::new();
+        dialog.set_title("Save As");
+
+        let filter = gtk::FileFilter::new();
+        filter.add_pattern("*.md");
+        filter.set_name(Some("Markdown files"));
+
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&filter);
+        dialog.set_filters(Some(&filters));
+
+        // Set initial folder
+        let initial_dir = self.imp().initial_dir.borrow().clone();
+        let initial_folder = gio::File::for_path(&initial_dir);
+        dialog.set_initial_folder(Some(&initial_folder));
+
+        let parent = self.root().and_downcast::<gtk::Window>();
+        let document = self.clone();
+        dialog.save(parent.as_ref(), None::<&gio::Cancellable>, move |result| {
+            match result.ok().and_then(|file| file.path()) {
+                Some(path) => {
+                    let path = if path.extension().is_none() {
+                        path.with_extension("md")
+                    } else {
+                        path
+                    };
+                    document.write_then(path, on_done);
+                }
+                // Cancelled, or a location without a local path
+                None => on_done(false),
+            }
+        });
+    }
+
+    /// Writes the document to `path`, which becomes its file, then calls
+    /// `on_done`. A failure is reported with the choice to retry or to save
+    /// somewhere else.
+    fn write_then<F>(&self, path: PathBuf, on_done: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        if self.changed_on_disk(&path) {
+            self.confirm_overwrite(path, on_done);
+            return;
+        }
+
+        match self.write_file(&path) {
+            Ok(()) => {
+                if self.file().as_deref() != Some(path.as_path()) {
+                    self.set_file(Some(path));
+                }
+                on_done(true);
+            }
+            Err(error) => self.show_save_error(error, on_done),
+        }
+    }
+
+    fn write_file(&self, path: &Path) -> Result<(), FileError> {
+        self.clean_up_for_save();
+        let backup = self.imp().preferences.borrow().backup;
+        files::write(path, &self.text(), backup)?;
+        self.remember_disk_state(path);
+        self.hide_disk_banner();
+        // Reset modified flag after successful save
+        self.set_modified(false);
+        self.discard_recovery();
+        add_to_recent(path);
+        Ok(())
+    }
+
+    /// Applies the file options to the buffer as one undoable edit, so the
+    /// editor shows exactly what is saved.
+    fn clean_up_for_save(&self) {
+        let Some(source_view) = self.imp().source_view.borrow().clone() else {
+            return;
+        };
+        let preferences = self.imp().preferences.borrow().clone();
+        let text = self.text();
+        let trailing = if preferences.trim_trailing_whitespace {
+            files::trailing_whitespace(&text)
+        } else {
+            Vec::new()
+        };
+        let add_newline =
+            preferences.ensure_trailing_newline && !text.is_empty() && !text.ends_with('\n');
+        if trailing.is_empty() && !add_newline {
+            return;
+        }
+
+        // The buffer counts characters, not bytes
+        let mut chars = 0;
+        let mut bytes = 0;
+        let mut ranges = Vec::with_capacity(trailing.len());
+        for range in trailing {
+            chars += text[bytes..range.start].chars().count() as i32;
+            let start = chars;
+            chars += text[range.clone()].chars().count() as i32;
+            ranges.push((start, chars));
+            bytes = range.end;
+        }
+
+        let buffer = source_view.buffer();
+        buffer.begin_user_action();
+        if add_newline {
+            buffer.insert(&mut buffer.end_iter(), "\n");
+        }
+        // From the end, so the earlier offsets stay valid
+        for (start, end) in ranges.into_iter().rev() {
+            let mut start = buffer.iter_at_offset(start);
+            let mut end = buffer.iter_at_offset(end);
+            buffer.delete(&mut start, &mut end);
+        }
+        buffer.end_user_action();
+    }
+
+    /// True when `path` is the document's file and another program changed
+    /// it since it was loaded or saved.
+    fn changed_on_disk(&self, path: &Path) -> bool {
+        if self.file().as_deref() != Some(path) {
+            return false;
+        }
+        let modified = files::modified(path);
+        modified.is_some() && modified != self.imp().disk_modified.get()
+    }
+
+    /// Asks before a save replaces a version of the file this document has
+    /// not seen.
+    fn confirm_overwrite<F>(&self, path: PathBuf, on_done: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        let dialog = adw::AlertDialog::builder()
+            .heading("File Changed on Disk")
+            .body(format!(
+                "“{}” was changed by another program. Saving will replace those changes with yours.",
+                self.title()
+            ))
+            .close_response("cancel")
+            .default_response("cancel")
+            .build();
+
+        dialog.add_response("cancel", "Cancel");
+        dialog.add_response("overwrite", "Save Anyway");
+        dialog.set_response_appearance("overwrite", adw::ResponseAppearance::Destructive);
+
+        let document = self.clone();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
+            if response == "overwrite" {
+                document.remember_disk_state(&path);
+                document.write_then(path, on_done);
+            } else {
+                on_done(false);
+            }
+        });
+    }
+
+    fn remember_disk_state(&self, path: &Path) {
+        self.imp().disk_modified.set(files::modified(path));
+    }
+
+    /// Watches the document's file for changes made by other programs.
+    fn watch_file(&self) {
+        let imp = self.imp();
+        if let Some(monitor) = imp.file_monitor.take() {
+            monitor.cancel();
+        }
+        let Some(path) = self.file() else {
+            return;
+        };
+
+        let file = gio::File::for_path(&path);
+        match file.monitor_file(gio::FileMonitorFlags::WATCH_MOVES, None::<&gio::Cancellable>) {
+            Ok(monitor) => {
+                let document = self.downgrade();
+                monitor.connect_changed(move |_, _, _, event| {
+                    // A write is a burst of Changed events ending in the hint
+                    if matches!(
+                        event,
+                        gio::FileMonitorEvent::Changed | gio::FileMonitorEvent::AttributeChanged
+                    ) {
+                        return;
+                    }
+                    if let Some(document) = document.upgrade() {
+                        document.check_disk();
+                    }
+                });
+                imp.file_monitor.replace(Some(monitor));
+            }
+            Err(e) => eprintln!("Error watching {}: {}", path.display(), e),
+        }
+    }
+
+    /// Follows a change to the file on disk: a document without unsaved
+    /// edits reloads, otherwise the banner asks what to do.
+    fn check_disk(&self) {
+        let Some(path) = self.file() else {
+            return;
+        };
+        let modified = files::modified(&path);
+        // Our own saves land here too
+        if modified == self.imp().disk_modified.get() {
+            return;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match modified {
+            None => self.show_disk_banner(
+                &format!("“{}” was deleted or moved by another program.", name),
+                false,
+            ),
+            Some(_) if !self.is_modified() => self.reload(),
+            Some(_) => self.show_disk_banner(
+                &format!("“{}” was changed by another program.", name),
+                true,
+            ),
+        }
+    }
+
+    fn show_disk_banner(&self, message: &str, changed: bool) {
+        let imp = self.imp();
+        if let Some(ref label) = *imp.disk_banner_label.borrow() {
+            label.set_text(message);
+        }
+        // A deleted file has nothing to reload or compare with
+        if let Some(ref button) = *imp.compare_btn.borrow() {
+            button.set_visible(changed);
+        }
+        if let Some(ref button) = *imp.reload_btn.borrow() {
+            button.set_visible(changed);
+        }
+        if let Some(ref banner) = *imp.disk_banner.borrow() {
+            banner.set_reveal_child(true);
+        }
+    }
+
+    fn hide_disk_banner(&self) {
+        if let Some(ref banner) = *self.imp().disk_banner.borrow() {
+            banner.set_reveal_child(false);
+        }
+    }
+
+    /// Replaces the text with the file on disk, dropping unsaved edits.
+    fn reload(&self) {
+        let Some(path) = self.file() else {
+            return;
+        };
+        // Keeps the cursor where it was
+        self.save_position();
+        match self.load_file(&path) {
+            Ok(()) => {
+                self.hide_disk_banner();
+                self.discard_recovery();
+            }
+            Err(error) => self.show_file_error(&error),
+        }
+    }
+
+    /// Keeps the edits over the version on disk; the next save replaces it
+    /// without asking.
+    fn keep_mine(&self) {
+        if let Some(path) = self.file() {
+            self.remember_disk_state(&path);
+        }
+        self.hide_disk_banner();
+    }
+
+    /// Shows the file on disk next to the edits.
+    fn show_disk_changes(&self) {
+        let Some(path) = self.file() else {
+            return;
+        };
+        let on_disk = match files::read(&path) {
+            Ok(text) => text,
+            Err(error) => {
+                self.show_file_error(&error);
+                return;
+            }
+        };
+
+        let style_scheme = self.imp().preferences.borrow().style_scheme.clone();
+        let style_scheme =
+            style_scheme_for(style_scheme.as_deref(), adw::StyleManager::default().is_dark());
+        let dialog = compare::dialog(
+            &format!("Changes to “{}”", self.title()),
+            ("On Disk", &on_disk),
+            ("Yours", &self.text()),
+            style_scheme.as_ref(),
+        );
+        dialog.present(Some(self));
+    }
+
+    /// Reports a failed read in the window the document is in.
+    fn show_file_error(&self, error: &FileError) {
+        match self.root().and_downcast::<crate::window::MyMarkdownWindow>() {
+            Some(window) => window.show_file_error(error),
+            None => eprintln!("{}", error),
+        }
+    }
+
+    /// Copies the text aside after a while, in case the app does not get
+    /// to save or close the document.
+    fn schedule_autosave(&self) {
+        let imp = self.imp();
+        if imp.autosave_source.borrow().is_some() {
+            return;
+        }
+
+        let interval = imp.preferences.borrow().autosave_interval;
+        let document = self.downgrade();
+        let source = glib::timeout_add_seconds_local_once(interval, move || {
+            if let Some(document) = document.upgrade() {
+                document.imp().autosave_source.take();
+                document.autosave();
+            }
+        });
+        imp.autosave_source.replace(Some(source));
+    }
+
+    fn autosave(&self) {
+        if !self.is_modified() {
+            return;
+        }
+        let id = self
+            .imp()
+            .recovery_id
+            .borrow_mut()
+            .get_or_insert_with(recovery::new_id)
+            .clone();
+        if let Err(e) = recovery::save(&id, self.file().as_deref(), &self.text()) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Drops the autosaved copy, once the document is saved or closed.
+    pub fn discard_recovery(&self) {
+        let imp = self.imp();
+        if let Some(source) = imp.autosave_source.take() {
+            source.remove();
+        }
+        if let Some(id) = imp.recovery_id.take() {
+            recovery::remove(&id);
+        }
+    }
+
+    /// Puts the text of an autosaved copy back, as unsaved changes.
+    ///
+    /// The copy is kept until this document is saved or closed.
+    pub fn restore_snapshot(&self, snapshot: Snapshot) {
+        if let Some(ref source_view) = *self.imp().source_view.borrow() {
+            source_view.buffer().set_text(&snapshot.text);
+        }
+        self.set_modified(true);
+        self.discard_recovery();
+        self.imp().recovery_id.replace(Some(snapshot.id));
+    }
+
+    fn show_save_error<F>(&self, error: FileError, on_done: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        eprintln!("{}", error);
+
+        let dialog = adw::AlertDialog::builder()
+            .heading("Could Not Save")
+            .body(format!("{} Your changes are still open in the editor.", error.message()))
+            .close_response("cancel")
+            .default_response("retry")
+            .build();
+
+        dialog.add_response("cancel", "Cancel");
+        dialog.add_response("save-as", "Save As…");
+        dialog.add_response("retry", "Retry");
+        dialog.set_response_appearance("retry", adw::ResponseAppearance::Suggested);
+
+        let document = self.clone();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
+            match response.as_str() {
+                "retry" => document.write_then(error.path, on_done),
+                "save-as" => document.save_file_as_then(on_done),
+                _ => on_done(false),
+            }
+        });
+    }
+
+    /// Updates the preview and the outline shortly after an edit.
+    fn schedule_preview_update(&self) {
+        let imp = self.imp();
+
+        // Skip if already pending or if neither is shown
+        if imp.preview_update_pending.get()
+            || (imp.view_mode.get() == ViewMode::Write && !self.is_outline_visible())
+        {
+            return;
+        }
+
+        imp.preview_update_pending.set(true);
+
+        // Debounce: wait for a pause in typing before updating
+        let delay = imp.preferences.borrow().preview_delay;
+        let document = self.clone();
+        glib::timeout_add_local_once(std::time::Duration::from_millis(delay.into()), move || {
+            document.imp().preview_update_pending.set(false);
+            document.update_outline();
+            document.update_preview();
+        });
+    }
+
+    /// Brings the preview up to date with the buffer.
+    ///
+    /// The page is loaded once; afterwards only the blocks that changed are
+    /// patched in through the bridge script, so scroll position and loaded
+    /// images survive edits.
+    fn update_preview(&self) {
+        let imp = self.imp();
+
+        if imp.view_mode.get() == ViewMode::Write {
+            return;
+        }
+
+        let Some(web_view) = imp.web_view.borrow().clone() else {
+            return;
+        };
+
+        // A full load is still in flight; it updates again once finished
+        if !imp.preview_ready.get() {
+            return;
+        }
+
+        let text = self.text();
+
+        // Relative URLs resolve against the base, and scripts are injected
+        // at load, so either changing needs a new page
+        let scripts_changed = !imp
+            .renderer
+            .borrow()
+            .page_scripts(&text)
+            .into_iter()
+            .eq(imp.preview_scripts.borrow().iter().map(String::as_str));
+        if scripts_changed || *imp.preview_base_uri.borrow() != self.base_uri() {
+            self.load_preview_content(&web_view, &text);
+            return;
+        }
+
+        let blocks = imp.renderer.borrow().render_blocks(&text);
+        self.render_pending_diagrams();
+        let old_blocks = imp.preview_blocks.replace(blocks.clone());
+        imp.preview_revision.set(imp.revision.get());
+        if let Some(js) = patch_script(&old_blocks, &blocks) {
+            web_view.evaluate_javascript(
+                &js,
+                None,
+                None,
+                None::<&gio::Cancellable>,
+                |_| {},
+            );
+        }
+
+        // Keep the preview at the editor's position as blocks change size
+        if imp.view_mode.get() == ViewMode::Split {
+            self.sync_preview_to_editor();
+        }
+    }
+
+    fn load_preview_content(&self, web_view: &webkit::WebView, markdown: &str) {
+        let imp = self.imp();
+        let full_html = imp.renderer.borrow().render(markdown);
+        let base_uri = self.base_uri();
+
+        // Only the diagram libraries the document uses, ahead of the bridge
+        let scripts: Vec<String> = imp
+            .renderer
+            .borrow()
+            .page_scripts(markdown)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if let Some(content_manager) = web_view.user_content_manager() {
+            content_manager.remove_all_scripts();
+            for script in scripts.iter().map(String::as_str).chain([PREVIEW_SCRIPT]) {
+                content_manager.add_script(&webkit::UserScript::new(
+                    script,
+                    webkit::UserContentInjectedFrames::TopFrame,
+                    webkit::UserScriptInjectionTime::End,
+                    &[],
+                    &[],
+                ));
+            }
+        }
+
+        imp.preview_ready.set(false);
+        imp.preview_blocks.replace(imp.renderer.borrow().render_blocks(markdown));
+        imp.preview_revision.set(imp.revision.get());
+        imp.preview_base_uri.replace(base_uri.clone());
+        imp.preview_scripts.replace(scripts);
+        web_view.load_html(&full_html, Some(&base_uri));
+        self.render_pending_diagrams();
+    }
+
+    /// Renders the diagrams the preview shows placeholders for in the
+    /// background, then updates the preview to patch them in.
+    fn render_pending_diagrams(&self) {
+        for job in self.imp().renderer.borrow().pending_diagrams() {
+            let document = self.downgrade();
+            glib::spawn_future_local(async move {
+                if gio::spawn_blocking(move || job.run()).await.is_err() {
+                    return;
+                }
+                if let Some(document) = document.upgrade() {
+                    document.schedule_preview_update();
+                }
+            });
+        }
+    }
+
+    /// Directory URI relative images and links in the preview resolve against.
+    ///
+    /// Untitled documents use the directory the app was started from.
+    fn base_uri(&self) -> String {
+        let imp = self.imp();
+        let dir = imp
+            .current_file
+            .borrow()
+            .as_ref()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| imp.initial_dir.borrow().clone());
+
+        // Without the trailing slash the last component would be dropped
+        let mut uri = gio::File::for_path(&dir).uri().to_string();
+        if !uri.ends_with('/') {
+            uri.push('/');
+        }
+        uri
+    }
+
+    /// Asks whether to save before the tab closes.
+    ///
+    /// `on_response` receives `true` once the tab may close, either because
+    /// the changes were saved or the user chose to discard them.
+    pub fn show_close_confirmation<F>(&self, on_response: F)
+    where
+        F: FnOnce(bool) + 'static,
+    {
+        let dialog = adw::AlertDialog::builder()
+            .heading("Save Changes?")
+            .body(format!("Do you want to save changes to “{}” before closing?", self.title()))
+            .close_response("cancel")
+            .default_response("save")
+            .build();
+
+        dialog.add_response("cancel", "Cancel");
+        dialog.add_response("discard", "Don't Save");
+        dialog.add_response("save", "Save");
+
+        dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+        dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+
+        let document = self.clone();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
+            match response.as_str() {
+                "save" => {
+                    // Close only once the document is actually on disk
+                    document.save_then(on_response);
+                }
+                "discard" => {
+                    on_response(true);
+                }
+                _ => {
+                    // Cancel - keep the tab open
+                    on_response(false);
+                }
+            }
+        });
+    }
+}
+
+/// Quotes `value` as a JavaScript string literal.
+fn js_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\u{2028}' => quoted.push_str("\\u2028"),
+            '\u{2029}' => quoted.push_str("\\u2029"),
+            '<' => quoted.push_str("\\u003c"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Builds the bridge call that turns the page showing `old` into `new`.
+///
+/// Blocks shared at the start and end are left alone; the rest are replaced
+/// and every block gets its (possibly shifted) source line. Returns `None`
+/// when nothing changed.
+fn patch_script(old: &[Block], new: &[Block]) -> Option<String> {
+    if old == new {
+        return None;
+    }
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a.html == b.html).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.html == b.html)
+        .count();
+
+    let inserted: Vec<String> = new[prefix..new.len() - suffix]
+        .iter()
+        .map(|block| format!("{{line: {}, html: {}}}", block.line, js_string(&block.html)))
+        .collect();
+    let lines: Vec<String> = new.iter().map(|block| block.line.to_string()).collect();
+
+    Some(format!(
+        "if (window.mymd) window.mymd.patch({}, {}, [{}], [{}]);",
+        prefix,
+        old.len() - prefix - suffix,
+        inserted.join(", "),
+        lines.join(", ")
+    ))
+}
+
+/// Adds `path` to the desktop's recently used files.
+fn add_to_recent(path: &Path) {
+    let uri = gio::File::for_path(path).uri();
+    gtk::RecentManager::default().add_item(&uri);
+}
+
+/// Editor style scheme for `id` in the light or dark style, the first of
+/// [`highlight::scheme_candidates`] that is installed.
+pub fn style_scheme_for(id: Option<&str>, dark: bool) -> Option<sourceview::StyleScheme> {
+    let scheme_manager = sourceview::StyleSchemeManager::default();
+    highlight::scheme_candidates(id, dark)
+        .iter()
+        .find_map(|candidate| scheme_manager.scheme(candidate))
+}
@@ -0,0 +1,314 @@
+//! Reading and writing documents.
+//!
+//! Saves are crash-safe: the text goes to a temporary file next to the
+//! document, is synced to disk and then renamed over it, so the document is
+//! always either the old or the new version. Failures carry the path and the
+//! operation, so the editor can tell the user what went wrong in a sentence
+//! instead of an `io::Error` dump.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, fchown};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Open,
+    Save,
+}
+
+/// Copy of the previous version kept on each save.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backup {
+    #[default]
+    None,
+    /// `notes.md~`, replaced on every save.
+    Simple,
+    /// `notes.md.~1~`, `notes.md.~2~`, … one per save.
+    Numbered,
+}
+
+impl Backup {
+    /// Name used in the settings.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backup::None => "none",
+            Backup::Simple => "simple",
+            Backup::Numbered => "numbered",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Backup::None),
+            "simple" => Some(Backup::Simple),
+            "numbered" => Some(Backup::Numbered),
+            _ => None,
+        }
+    }
+}
+
+/// A document that could not be opened or saved.
+#[derive(Debug)]
+pub struct FileError {
+    pub operation: Operation,
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+impl FileError {
+    pub fn new(operation: Operation, path: &Path, source: io::Error) -> Self {
+        Self {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Short description of the failure for a dialog or toast.
+    pub fn message(&self) -> String {
+        let name = self
+            .path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy();
+        let verb = match self.operation {
+            Operation::Open => "open",
+            Operation::Save => "save",
+        };
+
+        match (self.operation, self.source.kind()) {
+            (_, io::ErrorKind::PermissionDenied) => {
+                format!("You do not have permission to {} “{}”.", verb, name)
+            }
+            (Operation::Open, io::ErrorKind::NotFound) => format!("“{}” no longer exists.", name),
+            (Operation::Save, io::ErrorKind::NotFound) => {
+                format!("The folder for “{}” no longer exists.", name)
+            }
+            (Operation::Open, io::ErrorKind::InvalidData) => {
+                format!("“{}” is not a UTF-8 text file.", name)
+            }
+            (_, io::ErrorKind::IsADirectory) => format!("“{}” is a folder.", name),
+            (Operation::Save, io::ErrorKind::StorageFull) => {
+                format!("There is not enough space to save “{}”.", name)
+            }
+            (Operation::Save, io::ErrorKind::ReadOnlyFilesystem) => {
+                format!("“{}” is on a read-only disk.", name)
+            }
+            _ => format!("Could not {} “{}”: {}.", verb, name, self.source),
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.operation {
+            Operation::Open => "opening",
+            Operation::Save => "saving",
+        };
+        write!(f, "error {} {}: {}", verb, self.path.display(), self.source)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Reads a document as text.
+pub fn read(path: &Path) -> Result<String, FileError> {
+    fs::read_to_string(path).map_err(|e| FileError::new(Operation::Open, path, e))
+}
+
+/// When the file at `path` was last modified, `None` if it is missing.
+///
+/// Symlinks are followed, so this is the time of the file that is saved.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Writes `contents` to the document at `path`, keeping a backup of the
+/// previous version if asked to.
+///
+/// A symlinked document is saved through the link, and the file keeps its
+/// permissions and owner. When the owner cannot be kept (a file owned by
+/// someone else in a folder we can write to), the file is overwritten in
+/// place instead, which is not crash-safe but leaves ownership alone.
+pub fn write(path: &Path, contents: &str, backup: Backup) -> Result<(), FileError> {
+    let error = |e| FileError::new(Operation::Save, path, e);
+
+    // Follow symlinks so the link itself stays a link
+    let target = resolve_links(path).map_err(error)?;
+    let existing = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(error(e)),
+    };
+
+    if existing.is_some() {
+        write_backup(&target, backup).map_err(error)?;
+    }
+
+    match write_replacing(&target, contents, existing.as_ref()) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && existing.is_some() => {
+            write_in_place(&target, contents).map_err(error)
+        }
+        result => result.map_err(error),
+    }
+}
+
+/// The file `path` leads to through any symlinks, whether or not it exists,
+/// so a link to a file not created yet is saved by creating that file.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    // The same limit as Linux
+    const MAX_LINKS: usize = 40;
+
+    let mut target = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match fs::symlink_metadata(&target) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // A relative link is relative to the folder holding it
+                let link = fs::read_link(&target)?;
+                target = match target.parent() {
+                    Some(dir) => dir.join(link),
+                    None => link,
+                };
+            }
+            Ok(_) => return Ok(target),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(target),
+            Err(e) => return Err(e),
+        }
+    }
+    // Let the system report the loop
+    Err(fs::metadata(path)
+        .err()
+        .unwrap_or_else(|| io::Error::other("too many levels of symbolic links")))
+}
+
+/// Byte ranges of the spaces and tabs at line ends in `text`, for trimming
+/// before a save. The two-space hard breaks Markdown gives meaning to are
+/// kept.
+pub fn trailing_whitespace(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let stripped = content.trim_end_matches([' ', '\t']);
+        let hard_break = content.len() < line.len()
+            && !stripped.is_empty()
+            && content[stripped.len()..].starts_with("  ");
+        let keep = if hard_break { stripped.len() + 2 } else { stripped.len() };
+        if keep < content.len() {
+            ranges.push(start + keep..start + content.len());
+        }
+        start += line.len();
+    }
+    ranges
+}
+
+/// Writes to a temporary file in the same folder and renames it over `target`.
+fn write_replacing(target: &Path, contents: &str, existing: Option<&fs::Metadata>) -> io::Result<()> {
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // A new document gets the usual mode, minus the umask
+    let mode = existing.map_or(0o666, |metadata| metadata.mode() & 0o7777);
+    let (temp_path, mut temp) = create_temp_file(dir, target, mode)?;
+
+    let result = (|| {
+        // Permissions and owner come before the text, so a private document
+        // is never readable by others, not even in the temporary file
+        if let Some(metadata) = existing {
+            temp.set_permissions(metadata.permissions())?;
+            let created = temp.metadata()?;
+            if (created.uid(), created.gid()) != (metadata.uid(), metadata.gid()) {
+                fchown(&temp, Some(metadata.uid()), Some(metadata.gid()))?;
+            }
+        }
+        temp.write_all(contents.as_bytes())?;
+        temp.sync_all()?;
+        fs::rename(&temp_path, target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // Make the rename itself durable
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_in_place(target: &Path, contents: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).truncate(true).open(target)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Creates `.name.N.tmp` next to `target` with `mode`, picking the first
+/// free `N`.
+fn create_temp_file(dir: &Path, target: &Path, mode: u32) -> io::Result<(PathBuf, File)> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let mut attempt = std::process::id();
+    loop {
+        let temp_path = dir.join(format!(".{}.{}.tmp", name, attempt));
+        match OpenOptions::new().write(true).create_new(true).mode(mode).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt = attempt.wrapping_add(1),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Copies the current version of `target` aside before it is replaced.
+fn write_backup(target: &Path, backup: Backup) -> io::Result<()> {
+    let backup_path = match backup {
+        Backup::None => return Ok(()),
+        Backup::Simple => suffixed(target, "~"),
+        Backup::Numbered => suffixed(target, &format!(".~{}~", last_backup_number(target) + 1)),
+    };
+
+    // A copy rather than a hard link, which an in-place save would overwrite
+    fs::copy(target, &backup_path)?;
+    Ok(())
+}
+
+/// Highest `N` among the existing `name.~N~` backups of `target`, or 0.
+fn last_backup_number(target: &Path) -> u32 {
+    let (Some(dir), Some(name)) = (target.parent(), target.file_name()) else {
+        return 0;
+    };
+    let prefix = format!("{}.~", name.to_string_lossy());
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            file_name
+                .strip_prefix(&prefix)?
+                .strip_suffix('~')?
+                .parse::<u32>()
+                .ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,131 @@
+//! Syntax highlighting for fenced code blocks.
+//!
+//! Code is turned into spans carrying `hl-` prefixed scope classes, and the
+//! colors live in a separate stylesheet, so the same HTML works with any
+//! color theme.
+
+use std::sync::LazyLock;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Class prefix for highlighted spans; the block itself gets `hl-code`.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Code theme used alongside light editor color schemes.
+pub const LIGHT_THEME: &str = "InspiredGitHub";
+
+/// Code theme used alongside dark editor color schemes.
+pub const DARK_THEME: &str = "base16-ocean.dark";
+
+// Loading the bundled definitions takes a while, so do it once
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Highlights `code` written in the language named by a fence info string
+/// (`rust`, `py`, `sh`, ...).
+///
+/// Returns the inner HTML of the code block, or `None` when the language is
+/// unknown.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(language)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Stylesheet coloring highlighted code with the named theme.
+///
+/// Unknown names fall back to [`DARK_THEME`].
+pub fn stylesheet(theme: &str) -> String {
+    let theme = find_theme(theme);
+    css_for_theme_with_class_style(theme, CLASS_STYLE).unwrap_or_default()
+}
+
+/// Code theme matching a GtkSourceView style scheme id such as `Adwaita` or
+/// `solarized-dark`.
+pub fn theme_for_scheme(scheme: &str) -> &'static str {
+    match scheme {
+        "solarized-light" => "Solarized (light)",
+        "solarized-dark" => "Solarized (dark)",
+        "oblivion" | "cobalt" => "base16-eighties.dark",
+        scheme if scheme.ends_with("-dark") => DARK_THEME,
+        _ => LIGHT_THEME,
+    }
+}
+
+/// Style schemes that ship with GtkSourceView 5.
+const BUNDLED_SCHEMES: &[&str] = &[
+    "Adwaita",
+    "Adwaita-dark",
+    "classic",
+    "classic-dark",
+    "cobalt",
+    "kate",
+    "kate-dark",
+    "oblivion",
+    "solarized-dark",
+    "solarized-light",
+    "tango",
+];
+
+/// GtkSourceView scheme ids to try for the scheme `id` (`None` for the
+/// default) in the light or dark style, best first.
+///
+/// `solarized-light` is tried as `solarized-dark` in the dark style and the
+/// other way round; schemes without a variant come last, to be used as is.
+pub fn scheme_candidates(id: Option<&str>, dark: bool) -> Vec<String> {
+    match id {
+        Some(id) => {
+            let base = id
+                .strip_suffix("-dark")
+                .or_else(|| id.strip_suffix("-light"))
+                .unwrap_or(id);
+            if dark {
+                vec![format!("{base}-dark"), id.to_string()]
+            } else {
+                vec![format!("{base}-light"), base.to_string(), id.to_string()]
+            }
+        }
+        None if dark => vec!["Adwaita-dark".to_string(), "classic-dark".to_string()],
+        None => vec!["Adwaita".to_string(), "classic".to_string()],
+    }
+}
+
+/// Code theme for the scheme the editor picks for `id` in the given style.
+///
+/// For use without GtkSourceView, as on the command line: only the schemes
+/// GtkSourceView ships with are known to exist.
+pub fn theme_for_preferences(id: Option<&str>, dark: bool) -> &'static str {
+    let candidates = scheme_candidates(id, dark);
+    let scheme = candidates
+        .iter()
+        .find(|candidate| BUNDLED_SCHEMES.contains(&candidate.as_str()))
+        .or(candidates.last());
+    scheme.map_or(LIGHT_THEME, |scheme| theme_for_scheme(scheme))
+}
+
+fn find_theme(name: &str) -> &'static Theme {
+    THEMES
+        .themes
+        .get(name)
+        .unwrap_or_else(|| &THEMES.themes[DARK_THEME])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_theme_follows_the_scheme_variant() {
+        assert_eq!(theme_for_preferences(None, false), LIGHT_THEME);
+        assert_eq!(theme_for_preferences(None, true), DARK_THEME);
+        assert_eq!(theme_for_preferences(Some("solarized-light"), true), "Solarized (dark)");
+        assert_eq!(theme_for_preferences(Some("classic"), true), DARK_THEME);
+        // No light variant to switch to
+        assert_eq!(theme_for_preferences(Some("oblivion"), false), "base16-eighties.dark");
+    }
+}
@@ -0,0 +1,12 @@
+//! GTK-independent parts of MyMarkdown, shared by the editor and the command line.
+
+pub mod diagram;
+pub mod diff;
+pub mod files;
+pub mod highlight;
+pub mod math;
+pub mod metadata;
+pub mod positions;
+pub mod recovery;
+pub mod render;
+pub mod theme;
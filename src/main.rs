@@ -1,7 +1,13 @@
 mod app;
+mod cli;
+mod compare;
+mod document;
+mod preferences;
+mod settings;
 mod window;
 
 use app::MyMarkdownApp;
+use cli::Command;
 use gtk::prelude::*;
 use std::env;
 use std::path::PathBuf;
@@ -9,15 +15,23 @@ use std::path::PathBuf;
 fn main() {
     // Parse CLI arguments before GTK sees them
     let args: Vec<String> = env::args().collect();
-    let file_arg = args.get(1).cloned();
+
+    // Headless subcommands (render/export) never touch GTK
+    if let Some(command) = Command::parse(&args[1..]) {
+        let code = match command {
+            Ok(command) => command.run(),
+            Err(message) => cli::usage_error(&message),
+        };
+        std::process::exit(code);
+    }
 
     // Get current working directory for save dialog
     let initial_dir = env::current_dir().unwrap_or_else(|_| {
         dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"))
     });
 
-    // Create and run the application
-    // Pass empty args to GTK so it doesn't try to handle files
-    let app = MyMarkdownApp::new(file_arg, initial_dir);
-    app.run_with_args::<String>(&[]);
+    // Create and run the application. GTK turns the remaining arguments into
+    // files for `open`, forwarding them to an already running instance.
+    let app = MyMarkdownApp::new(initial_dir);
+    app.run_with_args(args.as_slice());
 }
@@ -0,0 +1,59 @@
+//! TeX math to MathML.
+//!
+//! WebKitGTK renders MathML natively, so `$…$` and `$$…$$` work offline in
+//! both the preview and exported pages without a JavaScript renderer.
+
+use crate::render::escape_html;
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+
+/// Converts inline (`$…$`) or display (`$$…$$`) math to MathML.
+///
+/// Malformed TeX is shown as written, marked with a `math-error` class and
+/// the parser's message as a tooltip.
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let style = if display {
+        DisplayStyle::Block
+    } else {
+        DisplayStyle::Inline
+    };
+
+    // Most parse errors come back inside the MathML rather than as an `Err`
+    let result = match latex_to_mathml(tex, style) {
+        Ok(mathml) => match mathml.split_once("[PARSE ERROR: ") {
+            Some((_, rest)) => Err(rest.split(']').next().unwrap_or_default().to_string()),
+            None => Ok(mathml),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+
+    match result {
+        Ok(mathml) => mathml,
+        Err(message) => {
+            let delimiter = if display { "$$" } else { "$" };
+            format!(
+                "<span class=\"math-error\" title=\"{}\">{}{}{}</span>",
+                escape_html(&message),
+                delimiter,
+                escape_html(tex),
+                delimiter
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_tex_is_shown_escaped() {
+        let html = to_mathml(r"\begin{foo} a<b \end{foo}", false);
+        assert!(html.starts_with("<span class=\"math-error\""));
+        assert!(html.ends_with(r">$\begin{foo} a&lt;b \end{foo}$</span>"));
+
+        // An unbalanced brace is reported inside the MathML
+        let html = to_mathml(r"\frac{a<b}", true);
+        assert!(html.starts_with("<span class=\"math-error\""));
+        assert!(html.ends_with(r">$$\frac{a&lt;b}$$</span>"));
+    }
+}
@@ -0,0 +1,196 @@
+//! Front matter at the top of a document.
+//!
+//! Both YAML (`---`) and TOML (`+++`) blocks are recognised. Only flat
+//! `key: value` / `key = value` lines are read; nested and multi-line values
+//! are left for the source editor.
+
+use pulldown_cmark::{Event, MetadataBlockKind, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+/// A top-level front matter field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub key: String,
+    /// Value with surrounding quotes removed.
+    pub value: String,
+    /// Byte range of the value as written in the document, quotes included.
+    pub value_range: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub format: Format,
+    pub fields: Vec<Field>,
+}
+
+impl FrontMatter {
+    /// Parses the front matter at the start of `markdown`, if there is one.
+    pub fn parse(markdown: &str) -> Option<Self> {
+        let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+            | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+        let mut events = Parser::new_ext(markdown, options).into_offset_iter();
+
+        let format = match events.next()? {
+            (Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)), _) => Format::Yaml,
+            (Event::Start(Tag::MetadataBlock(MetadataBlockKind::PlusesStyle)), _) => Format::Toml,
+            _ => return None,
+        };
+
+        let mut fields = Vec::new();
+        for (event, range) in events {
+            match event {
+                Event::Text(_) => fields.extend(parse_fields(format, markdown, range)),
+                Event::End(TagEnd::MetadataBlock(_)) => break,
+                _ => {}
+            }
+        }
+
+        Some(Self { format, fields })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.key == key)
+            .map(|field| field.value.as_str())
+    }
+
+    /// The `title` field, if set and not empty.
+    pub fn title(&self) -> Option<&str> {
+        self.get("title").filter(|title| !title.trim().is_empty())
+    }
+}
+
+/// Writes `value` the way it has to appear in the document, replacing a
+/// value that was written as `original`.
+///
+/// Quoting style is kept where possible; TOML strings are always quoted.
+pub fn format_value(format: Format, original: &str, value: &str) -> String {
+    if original.starts_with('\'') && format == Format::Yaml {
+        return format!("'{}'", value.replace('\'', "''"));
+    }
+
+    let needs_quotes = original.starts_with('"')
+        || match format {
+            Format::Yaml => yaml_needs_quotes(value),
+            Format::Toml => !is_toml_literal(value),
+        };
+
+    if needs_quotes {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Fields of the block content at `range` in `markdown`.
+fn parse_fields(format: Format, markdown: &str, range: Range<usize>) -> Vec<Field> {
+    let separator = match format {
+        Format::Yaml => ':',
+        Format::Toml => '=',
+    };
+
+    let mut fields = Vec::new();
+    let mut offset = range.start;
+    let mut lines = markdown[range].split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let start = offset;
+        offset += line.len();
+
+        // Keys after a TOML table header belong to that table
+        if format == Format::Toml && line.starts_with('[') {
+            break;
+        }
+        // Nested values and comments are not fields
+        if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, rest)) = line.split_once(separator) else {
+            continue;
+        };
+
+        let raw = rest.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        // A value running onto the lines below cannot be edited as one line
+        let continued = lines.peek().is_some_and(|next| next.starts_with([' ', '\t']));
+        if continued || spans_lines(format, raw) {
+            continue;
+        }
+        let value_start = start + key.len() + 1 + (rest.len() - rest.trim_start().len());
+
+        fields.push(Field {
+            key: key.trim().trim_matches('"').to_string(),
+            value: unquote(format, raw),
+            value_range: value_start..value_start + raw.len(),
+        });
+    }
+    fields
+}
+
+/// Whether `raw` opens a value whose content is on the following lines: a
+/// YAML block scalar, or a TOML multi-line string or array.
+fn spans_lines(format: Format, raw: &str) -> bool {
+    match format {
+        Format::Yaml => raw.starts_with(['|', '>']),
+        Format::Toml => {
+            raw.starts_with("\"\"\"")
+                || raw.starts_with("'''")
+                || (raw.starts_with('[') && !raw.ends_with(']'))
+                || (raw.starts_with('{') && !raw.ends_with('}'))
+        }
+    }
+}
+
+fn unquote(format: Format, raw: &str) -> String {
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        let mut value = String::new();
+        let mut chars = raw[1..raw.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some(next @ ('"' | '\\'))) => {
+                    value.push(next);
+                    chars.next();
+                }
+                (c, _) => value.push(c),
+            }
+        }
+        value
+    } else if raw.len() >= 2 && raw.starts_with('\'') && raw.ends_with('\'') {
+        // TOML literal strings have no escapes; YAML doubles the quote
+        let inner = &raw[1..raw.len() - 1];
+        match format {
+            Format::Yaml => inner.replace("''", "'"),
+            Format::Toml => inner.to_string(),
+        }
+    } else {
+        raw.to_string()
+    }
+}
+
+fn yaml_needs_quotes(value: &str) -> bool {
+    // Flow sequences like `[a, b]` stay lists
+    if value.starts_with('[') && value.ends_with(']') {
+        return false;
+    }
+    value.is_empty()
+        || value.trim() != value
+        || value.contains(": ")
+        || value.contains(" #")
+        || value.starts_with(|c| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+}
+
+fn is_toml_literal(value: &str) -> bool {
+    value == "true"
+        || value == "false"
+        || value.parse::<f64>().is_ok()
+        || (value.starts_with('[') && value.ends_with(']'))
+        || (value.starts_with('{') && value.ends_with('}'))
+}
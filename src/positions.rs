@@ -0,0 +1,89 @@
+//! Cursor and scroll positions of edited files.
+//!
+//! Positions are kept in `~/.local/state/mymd/positions`, one file per
+//! line, most recently closed first, so reopening a long document lands
+//! where it was left.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Number of files remembered.
+const LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    /// Cursor line, 0-based.
+    pub line: u32,
+    /// Cursor column, in characters.
+    pub column: u32,
+    /// Line at the top of the editor, 0-based.
+    pub top_line: u32,
+}
+
+/// The position `file` was last closed at.
+pub fn load(file: &Path) -> Option<Position> {
+    read_entries()
+        .into_iter()
+        .find(|(path, _)| path == file)
+        .map(|(_, position)| position)
+}
+
+/// Remembers `position` for `file`, dropping the oldest entries past the limit.
+pub fn save(file: &Path, position: Position) {
+    let Some(store) = store_path() else {
+        return;
+    };
+    // Paths are stored one per line
+    if file.to_str().is_none_or(|path| path.contains('\n')) {
+        return;
+    }
+
+    let mut entries = read_entries();
+    entries.retain(|(path, _)| path != file);
+    entries.insert(0, (file.to_path_buf(), position));
+    entries.truncate(LIMIT);
+
+    let content: String = entries
+        .iter()
+        .map(|(path, position)| {
+            format!(
+                "{}\t{}\t{}\t{}\n",
+                position.line,
+                position.column,
+                position.top_line,
+                path.display()
+            )
+        })
+        .collect();
+
+    let result = match store.parent() {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&store, content)),
+        None => fs::write(&store, content),
+    };
+    if let Err(e) = result {
+        eprintln!("Error saving cursor positions: {}", e);
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    dirs::state_dir().map(|dir| dir.join("mymd").join("positions"))
+}
+
+fn read_entries() -> Vec<(PathBuf, Position)> {
+    let Some(content) = store_path().and_then(|store| fs::read_to_string(store).ok()) else {
+        return Vec::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, '\t');
+            let position = Position {
+                line: parts.next()?.parse().ok()?,
+                column: parts.next()?.parse().ok()?,
+                top_line: parts.next()?.parse().ok()?,
+            };
+            Some((PathBuf::from(parts.next()?), position))
+        })
+        .collect()
+}
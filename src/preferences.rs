@@ -0,0 +1,299 @@
+use crate::app::MyMarkdownApp;
+use crate::settings::Preferences;
+use adw::prelude::*;
+use mymd::files::Backup;
+
+/// Builds the preferences dialog. Changes are saved and apply to all
+/// windows at once.
+pub fn dialog(app: &MyMarkdownApp) -> adw::PreferencesDialog {
+    let dialog = adw::PreferencesDialog::new();
+    let preferences = app.preferences();
+
+    let page = adw::PreferencesPage::builder()
+        .title("Editor")
+        .icon_name("document-edit-symbolic")
+        .build();
+    page.add(&appearance_group(app, &preferences));
+    page.add(&editing_group(app, &preferences));
+    dialog.add(&page);
+
+    let page = adw::PreferencesPage::builder()
+        .title("Preview")
+        .icon_name("view-reveal-symbolic")
+        .build();
+    page.add(&preview_group(app, &preferences));
+    dialog.add(&page);
+
+    let page = adw::PreferencesPage::builder()
+        .title("Files")
+        .icon_name("folder-documents-symbolic")
+        .build();
+    page.add(&startup_group(app, &preferences));
+    page.add(&files_group(app, &preferences));
+    dialog.add(&page);
+
+    dialog
+}
+
+fn appearance_group(app: &MyMarkdownApp, preferences: &Preferences) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder().title("Appearance").build();
+
+    // Schemes with a light and a dark variant are listed once
+    let scheme_manager = sourceview::StyleSchemeManager::default();
+    let mut scheme_ids: Vec<String> = Vec::new();
+    for id in scheme_manager.scheme_ids() {
+        let id = id.to_string();
+        let has_light = id.strip_suffix("-dark").is_some_and(|base| {
+            scheme_manager.scheme(base).is_some()
+                || scheme_manager.scheme(&format!("{base}-light")).is_some()
+        });
+        if has_light {
+            continue;
+        }
+        scheme_ids.push(id);
+    }
+
+    let names = gtk::StringList::new(&["Automatic"]);
+    for id in &scheme_ids {
+        let name = scheme_manager
+            .scheme(id)
+            .map(|scheme| scheme.name().to_string())
+            .unwrap_or_else(|| id.clone());
+        names.append(&name);
+    }
+
+    let selected = preferences
+        .style_scheme
+        .as_ref()
+        .and_then(|current| scheme_ids.iter().position(|id| id == current))
+        .map_or(0, |index| index as u32 + 1);
+
+    let row = adw::ComboRow::builder()
+        .title("Color Scheme")
+        .subtitle("Light and dark variants follow the system style")
+        .model(&names)
+        .selected(selected)
+        .build();
+    let application = app.clone();
+    row.connect_selected_notify(move |row| {
+        let scheme = match row.selected() {
+            0 | gtk::INVALID_LIST_POSITION => None,
+            index => scheme_ids.get(index as usize - 1).cloned(),
+        };
+        application.update_preferences(|preferences| preferences.style_scheme = scheme);
+    });
+    group.add(&row);
+
+    // Accent color: the system one, or a custom color for our windows
+    let system_row = adw::SwitchRow::builder()
+        .title("Use System Accent Color")
+        .active(preferences.accent_color.is_none())
+        .build();
+
+    let color_button = gtk::ColorDialogButton::new(Some(
+        gtk::ColorDialog::builder().with_alpha(false).build(),
+    ));
+    color_button.set_valign(gtk::Align::Center);
+    color_button.set_rgba(
+        &preferences
+            .accent_color()
+            .unwrap_or_else(|| adw::StyleManager::default().accent_color_rgba()),
+    );
+
+    let color_row = adw::ActionRow::builder()
+        .title("Accent Color")
+        .sensitive(preferences.accent_color.is_some())
+        .build();
+    color_row.add_suffix(&color_button);
+    color_row.set_activatable_widget(Some(&color_button));
+
+    let button = color_button.clone();
+    let row = color_row.clone();
+    let application = app.clone();
+    system_row.connect_active_notify(move |system_row| {
+        let system = system_row.is_active();
+        row.set_sensitive(!system);
+        let color = (!system).then(|| button.rgba().to_string());
+        application.update_preferences(|preferences| preferences.accent_color = color);
+    });
+
+    let switch = system_row.clone();
+    let application = app.clone();
+    color_button.connect_rgba_notify(move |button| {
+        if !switch.is_active() {
+            let color = Some(button.rgba().to_string());
+            application.update_preferences(|preferences| preferences.accent_color = color);
+        }
+    });
+
+    group.add(&system_row);
+    group.add(&color_row);
+    group
+}
+
+fn editing_group(app: &MyMarkdownApp, preferences: &Preferences) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder().title("Editing").build();
+
+    group.add(&spin_row(
+        app,
+        "Tab Width",
+        (1.0, 16.0, 1.0),
+        preferences.tab_width,
+        |preferences, value| preferences.tab_width = value,
+    ));
+    group.add(&switch_row(
+        app,
+        "Insert Spaces Instead of Tabs",
+        preferences.insert_spaces,
+        |preferences, active| preferences.insert_spaces = active,
+    ));
+    group.add(&switch_row(
+        app,
+        "Wrap Lines",
+        preferences.wrap_lines,
+        |preferences, active| preferences.wrap_lines = active,
+    ));
+    group.add(&switch_row(
+        app,
+        "Show Line Numbers",
+        preferences.show_line_numbers,
+        |preferences, active| preferences.show_line_numbers = active,
+    ));
+    group.add(&switch_row(
+        app,
+        "Highlight Current Line",
+        preferences.highlight_current_line,
+        |preferences, active| preferences.highlight_current_line = active,
+    ));
+
+    let row = spin_row(
+        app,
+        "Margin",
+        (0.0, 96.0, 2.0),
+        preferences.editor_margin,
+        |preferences, value| preferences.editor_margin = value,
+    );
+    row.set_subtitle("Space around the text, in pixels");
+    group.add(&row);
+
+    group
+}
+
+fn preview_group(app: &MyMarkdownApp, preferences: &Preferences) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder()
+        .title("Preview")
+        .description("The preview theme is picked from the main menu")
+        .build();
+
+    let row = spin_row(
+        app,
+        "Update Delay",
+        (0.0, 2000.0, 50.0),
+        preferences.preview_delay,
+        |preferences, value| preferences.preview_delay = value,
+    );
+    row.set_subtitle("Milliseconds to wait after typing before the preview refreshes");
+    group.add(&row);
+
+    group
+}
+
+fn startup_group(app: &MyMarkdownApp, preferences: &Preferences) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder().title("Startup and Recovery").build();
+
+    let row = switch_row(
+        app,
+        "Restore Previous Session",
+        preferences.restore_session,
+        |preferences, active| preferences.restore_session = active,
+    );
+    row.set_subtitle("Reopen the files that were open when MyMarkdown last quit");
+    group.add(&row);
+
+    let row = spin_row(
+        app,
+        "Autosave Interval",
+        (5.0, 3600.0, 5.0),
+        preferences.autosave_interval,
+        |preferences, value| preferences.autosave_interval = value,
+    );
+    row.set_subtitle("Seconds before unsaved changes are kept for recovery after a crash");
+    group.add(&row);
+
+    group
+}
+
+fn files_group(app: &MyMarkdownApp, preferences: &Preferences) -> adw::PreferencesGroup {
+    let group = adw::PreferencesGroup::builder().title("Saving").build();
+
+    let row = switch_row(
+        app,
+        "Remove Trailing Whitespace",
+        preferences.trim_trailing_whitespace,
+        |preferences, active| preferences.trim_trailing_whitespace = active,
+    );
+    row.set_subtitle("Two-space line breaks are kept");
+    group.add(&row);
+
+    let backups = [Backup::None, Backup::Simple, Backup::Numbered];
+    let row = adw::ComboRow::builder()
+        .title("Backups")
+        .subtitle("Keep the previous version when saving")
+        .model(&gtk::StringList::new(&["None", "file.md~", "file.md.~1~, ~2~, …"]))
+        .selected(backups.iter().position(|backup| *backup == preferences.backup).unwrap_or(0) as u32)
+        .build();
+    let application = app.clone();
+    row.connect_selected_notify(move |row| {
+        let backup = backups.get(row.selected() as usize).copied().unwrap_or_default();
+        application.update_preferences(|preferences| preferences.backup = backup);
+    });
+    group.add(&row);
+
+    group.add(&switch_row(
+        app,
+        "End Files With a Newline",
+        preferences.ensure_trailing_newline,
+        |preferences, active| preferences.ensure_trailing_newline = active,
+    ));
+
+    group
+}
+
+/// A switch bound to one boolean preference.
+fn switch_row<F>(app: &MyMarkdownApp, title: &str, active: bool, set: F) -> adw::SwitchRow
+where
+    F: Fn(&mut Preferences, bool) + 'static,
+{
+    let row = adw::SwitchRow::builder().title(title).active(active).build();
+    let app = app.clone();
+    row.connect_active_notify(move |row| {
+        let active = row.is_active();
+        app.update_preferences(|preferences| set(preferences, active));
+    });
+    row
+}
+
+/// A spin button bound to one numeric preference; `range` is
+/// `(min, max, step)`.
+fn spin_row<F>(
+    app: &MyMarkdownApp,
+    title: &str,
+    range: (f64, f64, f64),
+    value: u32,
+    set: F,
+) -> adw::SpinRow
+where
+    F: Fn(&mut Preferences, u32) + 'static,
+{
+    let (min, max, step) = range;
+    let row = adw::SpinRow::with_range(min, max, step);
+    row.set_title(title);
+    row.set_value(value.into());
+
+    let app = app.clone();
+    row.connect_value_notify(move |row| {
+        let value = row.value() as u32;
+        app.update_preferences(|preferences| set(preferences, value));
+    });
+    row
+}
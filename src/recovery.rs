@@ -0,0 +1,123 @@
+//! Autosaved copies of unsaved documents.
+//!
+//! While a document has unsaved changes its text is copied to
+//! `~/.local/share/mymd/recovery/` every so often. The copy is removed when
+//! the document is saved or closed, so any copy found at startup belongs to
+//! a session that ended without either.
+//!
+//! Each copy is a small header followed by a blank line and the text:
+//!
+//! ```text
+//! path: /home/me/notes.md
+//! saved: 1700000000
+//!
+//! # Notes
+//! ```
+
+use crate::files::{self, Backup, FileError};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An autosaved document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub id: String,
+    /// File the document belongs to, `None` for an untitled document.
+    pub path: Option<PathBuf>,
+    /// Seconds since the Unix epoch when the copy was written.
+    pub saved: u64,
+    pub text: String,
+}
+
+/// A fresh id, unique across documents and runs.
+pub fn new_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    format!(
+        "{}-{}-{}",
+        now(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Writes the copy for document `id`, replacing the previous one.
+pub fn save(id: &str, path: Option<&Path>, text: &str) -> Result<(), FileError> {
+    let Some(dir) = recovery_dir() else {
+        return Ok(());
+    };
+    let copy = dir.join(format!("{}.md", id));
+    fs::create_dir_all(&dir).map_err(|e| FileError::new(files::Operation::Save, &copy, e))?;
+
+    let path = path
+        .and_then(|path| path.to_str())
+        .filter(|path| !path.contains('\n'))
+        .unwrap_or_default();
+    let content = format!("path: {}\nsaved: {}\n\n{}", path, now(), text);
+    files::write(&copy, &content, Backup::None)
+}
+
+/// Removes the copy for document `id`, if there is one.
+pub fn remove(id: &str) {
+    let Some(dir) = recovery_dir() else {
+        return;
+    };
+    match fs::remove_file(dir.join(format!("{}.md", id))) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            eprintln!("Error removing recovery file: {}", e);
+        }
+        _ => {}
+    }
+}
+
+/// Every copy left behind, newest first.
+pub fn snapshots() -> Vec<Snapshot> {
+    let Some(entries) = recovery_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut snapshots: Vec<Snapshot> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file = entry.path();
+            let id = file.file_stem()?.to_str()?.to_string();
+            if file.extension()? != "md" {
+                return None;
+            }
+            parse(id, &fs::read_to_string(&file).ok()?)
+        })
+        .collect();
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.saved));
+    snapshots
+}
+
+fn parse(id: String, content: &str) -> Option<Snapshot> {
+    let (header, text) = content.split_once("\n\n")?;
+    let mut path = None;
+    let mut saved = 0;
+    for line in header.lines() {
+        match line.split_once(": ") {
+            Some(("path", value)) if !value.is_empty() => path = Some(PathBuf::from(value)),
+            Some(("saved", value)) => saved = value.parse().ok()?,
+            _ => {}
+        }
+    }
+
+    Some(Snapshot {
+        id,
+        path,
+        saved,
+        text: text.to_string(),
+    })
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("mymd").join("recovery"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,653 @@
+//! Markdown to HTML rendering.
+//!
+//! This module has no GTK dependency so the same pipeline can be shared by
+//! the live preview, HTML export and the command line.
+
+use crate::diagram::{DiagramJob, Diagrams};
+use crate::{highlight, math};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// HTML page the rendered body is wrapped in.
+///
+/// `{{stylesheet}}`, `{{body}}` and `{{scripts}}` are replaced with the
+/// stylesheet, the rendered markdown and the `<script>` elements diagram
+/// backends need respectively.
+pub const DEFAULT_TEMPLATE: &str = include_str!("../data/template.html");
+
+/// Preview stylesheet for dark mode (Yaru dark orange theme).
+pub const DARK_STYLESHEET: &str = include_str!("../data/preview-dark.css");
+
+/// Preview stylesheet for light mode (Yaru light orange theme).
+pub const LIGHT_STYLESHEET: &str = include_str!("../data/preview-light.css");
+
+/// Stylesheet used when none is chosen.
+pub const DEFAULT_STYLESHEET: &str = DARK_STYLESHEET;
+
+/// A top-level block of a rendered document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// 1-based source line the block starts on.
+    pub line: usize,
+    pub html: String,
+}
+
+/// A heading of a rendered document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    /// 1 for `#`, up to 6.
+    pub level: usize,
+    pub text: String,
+    /// Anchor id, unique within the document.
+    pub id: String,
+    /// 1-based source line.
+    pub line: usize,
+}
+
+/// Converts markdown documents into HTML.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    options: Options,
+    template: String,
+    stylesheet: String,
+    code_theme: String,
+    code_stylesheet: String,
+    diagrams: Arc<Diagrams>,
+    deferred_diagrams: bool,
+    source_lines: bool,
+    inline_scripts: bool,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer {
+    /// Creates a renderer with the default options, template and stylesheet.
+    pub fn new() -> Self {
+        Self {
+            options: Self::default_options(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            stylesheet: DEFAULT_STYLESHEET.to_string(),
+            code_theme: highlight::DARK_THEME.to_string(),
+            code_stylesheet: highlight::stylesheet(highlight::DARK_THEME),
+            diagrams: Arc::new(Diagrams::with_defaults()),
+            deferred_diagrams: false,
+            source_lines: true,
+            inline_scripts: true,
+        }
+    }
+
+    /// The pulldown-cmark extensions enabled by default.
+    pub fn default_options() -> Options {
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_FOOTNOTES);
+        options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_SMART_PUNCTUATION);
+        options.insert(Options::ENABLE_MATH);
+        options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+        options.insert(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+        options
+    }
+
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    pub fn with_stylesheet(mut self, stylesheet: impl Into<String>) -> Self {
+        self.stylesheet = stylesheet.into();
+        self
+    }
+
+    /// Colors highlighted code blocks with the named syntect theme, see
+    /// [`highlight::theme_for_scheme`].
+    pub fn with_code_theme(mut self, theme: impl Into<String>) -> Self {
+        self.code_theme = theme.into();
+        self.code_stylesheet = highlight::stylesheet(&self.code_theme);
+        self
+    }
+
+    /// Renders diagram fences with the given backends instead of the default
+    /// Graphviz, PlantUML and Mermaid ones.
+    pub fn with_diagrams(mut self, diagrams: Diagrams) -> Self {
+        self.diagrams = Arc::new(diagrams);
+        self
+    }
+
+    /// Renders diagrams from slow backends as placeholders instead of
+    /// waiting for them, and queues them for
+    /// [`Renderer::pending_diagrams`]. The preview uses this so typing never
+    /// waits on an external program.
+    pub fn with_deferred_diagrams(mut self, deferred: bool) -> Self {
+        self.deferred_diagrams = deferred;
+        self
+    }
+
+    /// Wraps each top-level block in `<div data-line="N">`, where `N` is the
+    /// 1-based source line it starts on. Enabled by default; the preview uses
+    /// these markers to keep the editor and the preview in sync.
+    pub fn with_source_lines(mut self, source_lines: bool) -> Self {
+        self.source_lines = source_lines;
+        self
+    }
+
+    /// Puts the scripts from [`Renderer::page_scripts`] in the page. Enabled
+    /// by default; the preview, whose pages may not run `<script>` elements,
+    /// injects them itself instead.
+    pub fn with_inline_scripts(mut self, inline_scripts: bool) -> Self {
+        self.inline_scripts = inline_scripts;
+        self
+    }
+
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn stylesheet(&self) -> &str {
+        &self.stylesheet
+    }
+
+    pub fn code_theme(&self) -> &str {
+        &self.code_theme
+    }
+
+    /// Everything that goes in the page's `<style>`: the stylesheet followed
+    /// by the code theme.
+    pub fn page_stylesheet(&self) -> String {
+        format!("{}\n{}", self.stylesheet, self.code_stylesheet)
+    }
+
+    /// Scripts the page for `markdown` needs to draw its client-side
+    /// diagrams. Only the fence languages the document uses count.
+    pub fn page_scripts(&self, markdown: &str) -> Vec<&str> {
+        let languages: Vec<String> = Parser::new_ext(markdown, self.options)
+            .filter_map(|event| match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    info.split_whitespace().next().map(str::to_string)
+                }
+                _ => None,
+            })
+            .collect();
+        self.diagrams.scripts_for(&languages)
+    }
+
+    /// Diagrams that rendered as placeholders since the last call, see
+    /// [`Renderer::with_deferred_diagrams`].
+    pub fn pending_diagrams(&self) -> Vec<DiagramJob> {
+        self.diagrams.take_pending()
+    }
+
+    /// Renders `markdown` to an HTML fragment, without the page template.
+    pub fn render_body(&self, markdown: &str) -> String {
+        let mut html_output = String::new();
+        for block in self.render_blocks(markdown) {
+            if self.source_lines {
+                html_output.push_str(&format!("<div data-line=\"{}\">\n", block.line));
+                html_output.push_str(&block.html);
+                html_output.push_str("</div>\n");
+            } else {
+                html_output.push_str(&block.html);
+            }
+        }
+        html_output
+    }
+
+    /// Renders `markdown` to its top-level blocks, in document order.
+    pub fn render_blocks(&self, markdown: &str) -> Vec<Block> {
+        let lines = LineIndex::new(markdown);
+        let outline = self.outline(markdown);
+        let mut heading_ids = outline.iter().map(|heading| heading.id.clone());
+        let mut footnotes = FootnoteNumbers::default();
+        let mut code = CodeBlocks::new(&self.diagrams, self.deferred_diagrams);
+        let mut blocks = Vec::new();
+        let mut events = Vec::new();
+        let mut depth = 0usize;
+        let mut line = 1;
+        // Front matter is metadata, see `metadata::FrontMatter`
+        let mut in_metadata = false;
+        let mut in_toc = false;
+
+        for (event, range) in Parser::new_ext(markdown, self.options).into_offset_iter() {
+            if depth == 0 {
+                line = lines.line_of(range.start);
+            }
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            let event = match event {
+                Event::Start(Tag::MetadataBlock(_)) => {
+                    in_metadata = true;
+                    None
+                }
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    in_metadata = false;
+                    None
+                }
+                _ if in_metadata => None,
+                // A paragraph of just `[TOC]` becomes a table of contents
+                Event::Start(Tag::Paragraph) if depth == 1 && markdown[range].trim() == "[TOC]" => {
+                    in_toc = true;
+                    None
+                }
+                Event::End(TagEnd::Paragraph) if in_toc && depth == 0 => {
+                    in_toc = false;
+                    Some(Event::Html(CowStr::from(toc_html(&outline))))
+                }
+                _ if in_toc => None,
+                Event::Start(Tag::Heading { level, id: _, classes, attrs }) => {
+                    let id = heading_ids.next().map(CowStr::from);
+                    Some(Event::Start(Tag::Heading { level, id, classes, attrs }))
+                }
+                Event::TaskListMarker(checked) => Some(task_checkbox(checked)),
+                Event::InlineMath(tex) => Some(Event::InlineHtml(math::to_mathml(&tex, false).into())),
+                Event::DisplayMath(tex) => Some(Event::InlineHtml(math::to_mathml(&tex, true).into())),
+                event => code.rewrite(event).map(|event| footnotes.rewrite(event)),
+            };
+            events.extend(event);
+
+            if depth == 0 && !events.is_empty() {
+                let mut html_output = String::new();
+                html::push_html(&mut html_output, events.drain(..));
+                blocks.push(Block { line, html: html_output });
+            }
+        }
+
+        blocks
+    }
+
+    /// The document's headings in order, with the anchor ids the rendered
+    /// HTML gives them.
+    ///
+    /// Ids follow GitHub's scheme, so `#links` written for GitHub work here.
+    pub fn outline(&self, markdown: &str) -> Vec<Heading> {
+        let lines = LineIndex::new(markdown);
+        let mut slugs = Slugs::default();
+        let mut headings = Vec::new();
+        let mut current: Option<Heading> = None;
+
+        for (event, range) in Parser::new_ext(markdown, self.options).into_offset_iter() {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    current = Some(Heading {
+                        level: level as usize,
+                        text: String::new(),
+                        id: String::new(),
+                        line: lines.line_of(range.start),
+                    });
+                }
+                Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                    if let Some(heading) = current.as_mut() {
+                        heading.text.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let Some(mut heading) = current.take() {
+                        heading.id = slugs.unique(&heading.text);
+                        headings.push(heading);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        headings
+    }
+
+    /// Byte ranges of the `[ ]` / `[x]` task list markers in `markdown`, in
+    /// the order their checkboxes appear in the rendered HTML.
+    pub fn task_markers(&self, markdown: &str) -> Vec<Range<usize>> {
+        Parser::new_ext(markdown, self.options)
+            .into_offset_iter()
+            .filter(|(event, _)| matches!(event, Event::TaskListMarker(_)))
+            .map(|(_, range)| range)
+            .collect()
+    }
+
+    /// Renders `markdown` to a complete HTML page.
+    ///
+    /// Only the diagram scripts the document uses are included.
+    pub fn render(&self, markdown: &str) -> String {
+        let body = self.render_body(markdown);
+        let stylesheet = self.page_stylesheet();
+        let scripts: String = if self.inline_scripts {
+            self.page_scripts(markdown)
+                .iter()
+                .map(|script| format!("<script>\n{}</script>\n", script))
+                .collect()
+        } else {
+            String::new()
+        };
+        fill_template(
+            &self.template,
+            &[("stylesheet", &stylesheet), ("body", &body), ("scripts", &scripts)],
+        )
+    }
+}
+
+/// Replaces each `{{name}}` placeholder in `template` with its value.
+///
+/// Substitution happens in a single pass, so values are never rescanned for
+/// placeholders. Unknown placeholders are left untouched.
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        let replacement = after.find("}}").and_then(|end| {
+            let name = after[..end].trim();
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, end + 2))
+        });
+
+        match replacement {
+            Some((value, consumed)) => {
+                output.push_str(value);
+                rest = &after[consumed..];
+            }
+            None => {
+                output.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Nested list of links to `outline`, for `[TOC]`.
+fn toc_html(outline: &[Heading]) -> String {
+    let mut html_output = String::from("<nav class=\"toc\">\n");
+    let mut levels: Vec<usize> = Vec::new();
+
+    for heading in outline {
+        while levels.last().is_some_and(|&level| level > heading.level) {
+            html_output.push_str("</li>\n</ul>\n");
+            levels.pop();
+        }
+        if levels.last() == Some(&heading.level) {
+            html_output.push_str("</li>\n");
+        } else {
+            html_output.push_str("<ul>\n");
+            levels.push(heading.level);
+        }
+        html_output.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape_html(&heading.id),
+            escape_html(&heading.text)
+        ));
+    }
+    for _ in levels {
+        html_output.push_str("</li>\n</ul>\n");
+    }
+
+    html_output.push_str("</nav>\n");
+    html_output
+}
+
+/// Hands out GitHub-style heading anchors, numbering repeats.
+#[derive(Default)]
+struct Slugs {
+    /// Every slug handed out, with the last number appended to it.
+    seen: HashMap<String, usize>,
+}
+
+impl Slugs {
+    /// Numbered slugs are recorded too, so `A`, `A`, `A-1` give `a`, `a-1`,
+    /// `a-1-1` like github-slugger.
+    fn unique(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+        while self.seen.contains_key(&slug) {
+            let count = self.seen.entry(base.clone()).or_insert(0);
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// GitHub's heading anchor: lowercased, punctuation dropped, spaces turned
+/// into hyphens.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Task list checkbox, classed so the preview can tell it apart from raw
+/// `<input>` elements in the document.
+fn task_checkbox(checked: bool) -> Event<'static> {
+    let checked = if checked { " checked=\"\"" } else { "" };
+    Event::InlineHtml(CowStr::from(format!(
+        "<input class=\"task-list-item-checkbox\" disabled=\"\" type=\"checkbox\"{}/>\n",
+        checked
+    )))
+}
+
+/// Maps byte offsets to 1-based line numbers.
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+}
+
+/// Numbers footnotes across the whole document.
+///
+/// Blocks are rendered one at a time, so the numbering pulldown-cmark's HTML
+/// writer keeps per call would restart in every block. Footnote events are
+/// turned into raw HTML with document-wide numbers instead.
+#[derive(Default)]
+struct FootnoteNumbers {
+    numbers: HashMap<String, usize>,
+}
+
+impl FootnoteNumbers {
+    fn number(&mut self, name: &str) -> usize {
+        let next = self.numbers.len() + 1;
+        *self.numbers.entry(name.to_string()).or_insert(next)
+    }
+
+    fn rewrite<'a>(&mut self, event: Event<'a>) -> Event<'a> {
+        match event {
+            Event::FootnoteReference(name) => {
+                let number = self.number(&name);
+                Event::InlineHtml(CowStr::from(format!(
+                    "<sup class=\"footnote-reference\"><a href=\"#{}\">{}</a></sup>",
+                    escape_html(&name),
+                    number
+                )))
+            }
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                let number = self.number(&name);
+                Event::Html(CowStr::from(format!(
+                    "<div class=\"footnote-definition\" id=\"{}\"><sup class=\"footnote-definition-label\">{}</sup>",
+                    escape_html(&name),
+                    number
+                )))
+            }
+            Event::End(TagEnd::FootnoteDefinition) => Event::Html(CowStr::from("</div>\n")),
+            event => event,
+        }
+    }
+}
+
+/// Highlights fenced code blocks and draws diagram fences.
+///
+/// The text of a code block is collected until the block ends and then
+/// emitted as a single raw HTML event. Blocks in unknown languages keep
+/// pulldown-cmark's plain markup.
+struct CodeBlocks<'r> {
+    diagrams: &'r Diagrams,
+    /// Whether slow diagrams render as placeholders.
+    deferred: bool,
+    /// Language and text of the code block being collected.
+    current: Option<(String, String)>,
+}
+
+impl<'r> CodeBlocks<'r> {
+    fn new(diagrams: &'r Diagrams, deferred: bool) -> Self {
+        Self { diagrams, deferred, current: None }
+    }
+
+    fn rewrite<'a>(&mut self, event: Event<'a>) -> Option<Event<'a>> {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or_default().to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.current = Some((language, String::new()));
+                None
+            }
+            Event::Text(text) if self.current.is_some() => {
+                if let Some((_, code)) = self.current.as_mut() {
+                    code.push_str(&text);
+                }
+                None
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let (language, code) = self.current.take().unwrap_or_default();
+                let diagram = if self.deferred {
+                    self.diagrams.render_deferred(&language, &code)
+                } else {
+                    self.diagrams.render(&language, &code)
+                };
+                let html = diagram.unwrap_or_else(|| code_block(&language, &code));
+                Some(Event::Html(CowStr::from(html)))
+            }
+            event => Some(event),
+        }
+    }
+}
+
+fn code_block(language: &str, code: &str) -> String {
+    if language.is_empty() {
+        return format!("<pre><code>{}</code></pre>\n", escape_html(code));
+    }
+
+    let class = format!("language-{}", escape_html(language));
+    match highlight::highlight(language, code) {
+        Some(html) => format!("<pre class=\"hl-code\"><code class=\"{}\">{}</code></pre>\n", class, html),
+        None => format!("<pre><code class=\"{}\">{}</code></pre>\n", class, escape_html(code)),
+    }
+}
+
+/// Escapes text for use in HTML content and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_template_replaces_known_placeholders_once() {
+        let values = [("body", "<p>{{stylesheet}}</p>"), ("stylesheet", "css")];
+        assert_eq!(
+            fill_template("<style>{{ stylesheet }}</style>{{body}}", &values),
+            "<style>css</style><p>{{stylesheet}}</p>"
+        );
+        // Unknown and unterminated placeholders stay as they are
+        assert_eq!(fill_template("{{unknown}} {{body", &values), "{{unknown}} {{body");
+    }
+
+    #[test]
+    fn slugify_follows_github() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Setup & Install "), "setup--install");
+        assert_eq!(slugify("snake_case and-dashes"), "snake_case-and-dashes");
+        assert_eq!(slugify("Ünïcode Überschrift"), "ünïcode-überschrift");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn render_blocks_start_on_their_source_line() {
+        let markdown = "---\ntitle: T\n---\n\n# One\n\ntext\nmore\n\n- a\n- b\n";
+        let blocks = Renderer::new().render_blocks(markdown);
+
+        // The front matter is left out
+        let lines: Vec<usize> = blocks.iter().map(|block| block.line).collect();
+        assert_eq!(lines, [5, 7, 10]);
+        assert_eq!(blocks[0].html, "<h1 id=\"one\">One</h1>\n");
+        assert_eq!(blocks[1].html, "<p>text\nmore</p>\n");
+        assert!(blocks[2].html.starts_with("<ul>"));
+    }
+
+    #[test]
+    fn pages_inline_only_the_scripts_they_use() {
+        let mut diagrams = Diagrams::new();
+        diagrams.register(crate::diagram::MermaidBackend);
+        let renderer = Renderer::new().with_diagrams(diagrams);
+        let mermaid = "```mermaid\ngraph TD; A-->B;\n```\n";
+
+        assert!(renderer.page_scripts("```rust\nfn main() {}\n```\n").is_empty());
+        assert_eq!(renderer.page_scripts(mermaid).len(), 1);
+        assert!(renderer.render(mermaid).contains("<script>"));
+        assert!(!renderer.with_inline_scripts(false).render(mermaid).contains("<script>"));
+    }
+
+    #[test]
+    fn repeated_headings_get_numbered_slugs() {
+        let mut slugs = Slugs::default();
+        let ids: Vec<String> = ["A", "A", "A-1", "A"].iter().map(|text| slugs.unique(text)).collect();
+        assert_eq!(ids, ["a", "a-1", "a-1-1", "a-2"]);
+    }
+}
@@ -0,0 +1,156 @@
+//! User preferences, stored with GSettings.
+//!
+//! The `org.gnome.MyMarkdown` schema is installed by `install.sh`. When it
+//! is missing, as when running from the build tree, preferences still apply
+//! but only last until the app quits.
+
+use gtk::prelude::*;
+use gtk::{gdk, gio};
+use mymd::files::Backup;
+use mymd::theme::PreviewTheme;
+
+pub const SCHEMA_ID: &str = "org.gnome.MyMarkdown";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preferences {
+    /// Editor style scheme id, `None` for Adwaita.
+    pub style_scheme: Option<String>,
+    /// Accent color as a CSS color, `None` for the system accent.
+    pub accent_color: Option<String>,
+    pub tab_width: u32,
+    pub insert_spaces: bool,
+    pub wrap_lines: bool,
+    pub show_line_numbers: bool,
+    pub highlight_current_line: bool,
+    pub editor_margin: u32,
+    pub preview_theme: PreviewTheme,
+    /// Preview debounce after an edit, in milliseconds.
+    pub preview_delay: u32,
+    pub trim_trailing_whitespace: bool,
+    pub ensure_trailing_newline: bool,
+    pub backup: Backup,
+    /// Seconds between copies of unsaved changes for crash recovery.
+    pub autosave_interval: u32,
+    /// Reopen the files of the last session on startup.
+    pub restore_session: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            style_scheme: None,
+            accent_color: None,
+            tab_width: 4,
+            insert_spaces: true,
+            wrap_lines: true,
+            show_line_numbers: true,
+            highlight_current_line: true,
+            editor_margin: 12,
+            preview_theme: PreviewTheme::System,
+            preview_delay: 150,
+            trim_trailing_whitespace: false,
+            ensure_trailing_newline: false,
+            backup: Backup::None,
+            autosave_interval: 30,
+            restore_session: false,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn load(settings: &gio::Settings) -> Self {
+        Self {
+            style_scheme: non_empty(settings.string("style-scheme")),
+            accent_color: non_empty(settings.string("accent-color")),
+            tab_width: settings.uint("tab-width"),
+            insert_spaces: settings.boolean("insert-spaces"),
+            wrap_lines: settings.boolean("wrap-lines"),
+            show_line_numbers: settings.boolean("show-line-numbers"),
+            highlight_current_line: settings.boolean("highlight-current-line"),
+            editor_margin: settings.uint("editor-margin"),
+            preview_theme: PreviewTheme::from_setting(&settings.string("preview-theme")),
+            preview_delay: settings.uint("preview-delay"),
+            trim_trailing_whitespace: settings.boolean("trim-trailing-whitespace"),
+            ensure_trailing_newline: settings.boolean("ensure-trailing-newline"),
+            backup: Backup::from_name(&settings.string("backup")).unwrap_or_default(),
+            autosave_interval: settings.uint("autosave-interval"),
+            restore_session: settings.boolean("restore-session"),
+        }
+    }
+
+    /// Writes every key at once, so listeners see a single change.
+    ///
+    /// The keys go through their own `gio::Settings` in delay mode: putting
+    /// the app's shared instance in delay mode would hold back every later
+    /// write to it, such as the window state.
+    pub fn save(&self) {
+        let settings = gio::Settings::new(SCHEMA_ID);
+        settings.delay();
+        let results = [
+            settings.set_string("style-scheme", self.style_scheme.as_deref().unwrap_or_default()),
+            settings.set_string("accent-color", self.accent_color.as_deref().unwrap_or_default()),
+            settings.set_uint("tab-width", self.tab_width),
+            settings.set_boolean("insert-spaces", self.insert_spaces),
+            settings.set_boolean("wrap-lines", self.wrap_lines),
+            settings.set_boolean("show-line-numbers", self.show_line_numbers),
+            settings.set_boolean("highlight-current-line", self.highlight_current_line),
+            settings.set_uint("editor-margin", self.editor_margin),
+            settings.set_string("preview-theme", &self.preview_theme.to_setting()),
+            settings.set_uint("preview-delay", self.preview_delay),
+            settings.set_boolean("trim-trailing-whitespace", self.trim_trailing_whitespace),
+            settings.set_boolean("ensure-trailing-newline", self.ensure_trailing_newline),
+            settings.set_string("backup", self.backup.name()),
+            settings.set_uint("autosave-interval", self.autosave_interval),
+            settings.set_boolean("restore-session", self.restore_session),
+        ];
+        settings.apply();
+
+        for result in results {
+            if let Err(e) = result {
+                eprintln!("Error saving preferences: {}", e);
+            }
+        }
+    }
+
+    pub fn accent_color(&self) -> Option<gdk::RGBA> {
+        self.accent_color
+            .as_deref()
+            .and_then(|color| gdk::RGBA::parse(color).ok())
+    }
+}
+
+/// The app settings, or `None` when the schema is not installed.
+pub fn settings() -> Option<gio::Settings> {
+    let settings = lookup(SCHEMA_ID);
+    if settings.is_none() {
+        eprintln!(
+            "Settings schema {} is not installed; preferences will not be saved",
+            SCHEMA_ID
+        );
+    }
+    settings
+}
+
+/// The saved preferences, without a warning when there are none.
+pub fn saved_preferences() -> Option<Preferences> {
+    lookup(SCHEMA_ID).map(|settings| Preferences::load(&settings))
+}
+
+/// Whether the desktop asks for the dark style, which libadwaita follows.
+pub fn prefers_dark() -> bool {
+    const INTERFACE_SCHEMA: &str = "org.gnome.desktop.interface";
+    // Older desktops have the schema but not the key
+    let has_key = gio::SettingsSchemaSource::default()
+        .and_then(|source| source.lookup(INTERFACE_SCHEMA, true))
+        .is_some_and(|schema| schema.has_key("color-scheme"));
+    has_key && gio::Settings::new(INTERFACE_SCHEMA).string("color-scheme") == "prefer-dark"
+}
+
+fn lookup(schema_id: &str) -> Option<gio::Settings> {
+    gio::SettingsSchemaSource::default()?.lookup(schema_id, true)?;
+    Some(gio::Settings::new(schema_id))
+}
+
+fn non_empty(value: impl Into<String>) -> Option<String> {
+    Some(value.into()).filter(|value| !value.is_empty())
+}
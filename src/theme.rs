@@ -0,0 +1,82 @@
+//! Preview themes.
+//!
+//! The bundled light and dark stylesheets follow the system style. Any
+//! `*.css` file in `~/.config/mymd/themes/` can be picked instead.
+
+use crate::render::{DARK_STYLESHEET, LIGHT_STYLESHEET};
+use std::fs;
+use std::path::PathBuf;
+
+/// A stylesheet the preview can use.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum PreviewTheme {
+    /// The bundled stylesheet matching the system light/dark style.
+    #[default]
+    System,
+    /// A user stylesheet, used as is in both styles.
+    Custom(PathBuf),
+}
+
+impl PreviewTheme {
+    /// Theme from its saved form: a CSS file path, or `""` for the bundled one.
+    pub fn from_setting(value: &str) -> Self {
+        if value.is_empty() {
+            PreviewTheme::System
+        } else {
+            PreviewTheme::Custom(PathBuf::from(value))
+        }
+    }
+
+    /// The inverse of [`PreviewTheme::from_setting`].
+    pub fn to_setting(&self) -> String {
+        match self {
+            PreviewTheme::System => String::new(),
+            PreviewTheme::Custom(path) => path.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Stylesheet for this theme in the given style.
+    ///
+    /// An unreadable custom theme falls back to the bundled stylesheet.
+    pub fn stylesheet(&self, dark: bool) -> String {
+        let bundled = if dark { DARK_STYLESHEET } else { LIGHT_STYLESHEET };
+        match self {
+            PreviewTheme::System => bundled.to_string(),
+            PreviewTheme::Custom(path) => fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Error loading theme {}: {}", path.display(), e);
+                bundled.to_string()
+            }),
+        }
+    }
+
+    /// Name shown in menus: the file name without `.css`.
+    pub fn name(&self) -> String {
+        match self {
+            PreviewTheme::System => "Default".to_string(),
+            PreviewTheme::Custom(path) => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// Directory user themes are read from.
+pub fn themes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("mymd").join("themes"))
+}
+
+/// User themes, sorted by name.
+pub fn custom_themes() -> Vec<PreviewTheme> {
+    let Some(entries) = themes_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "css"))
+        .collect();
+    paths.sort();
+    paths.into_iter().map(PreviewTheme::Custom).collect()
+}
@@ -1,44 +1,34 @@
+use crate::app::MyMarkdownApp;
+use crate::compare;
+use crate::document::{MyMarkdownDocument, ViewMode, style_scheme_for};
+use crate::preferences;
 use adw::prelude::*;
 use adw::subclass::prelude::*;
 use gtk::{gdk, gio, glib};
-use pulldown_cmark::{html, Options, Parser};
-use sourceview::prelude::*;
-use webkit::prelude::*;
+use mymd::files::{self, FileError};
+use mymd::recovery::{self, Snapshot};
+use mymd::theme::{self, PreviewTheme};
 use std::cell::{Cell, RefCell};
-use std::fs;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
-pub enum ViewMode {
-    #[default]
-    Write,
-    Preview,
-    Split,
-}
-
 mod imp {
     use super::*;
 
     #[derive(Default)]
     pub struct MyMarkdownWindow {
-        pub source_view: RefCell<Option<sourceview::View>>,
-        pub web_view: RefCell<Option<webkit::WebView>>,
-        pub current_file: RefCell<Option<PathBuf>>,
-        pub view_mode: Cell<ViewMode>,
+        pub tab_view: RefCell<Option<adw::TabView>>,
+        pub toast_overlay: RefCell<Option<adw::ToastOverlay>>,
         pub updating: Cell<bool>,
-        pub paned: RefCell<Option<gtk::Paned>>,
-        pub editor_frame: RefCell<Option<gtk::Frame>>,
-        pub preview_frame: RefCell<Option<gtk::Frame>>,
         pub write_btn: RefCell<Option<gtk::ToggleButton>>,
         pub preview_btn: RefCell<Option<gtk::ToggleButton>>,
         pub split_btn: RefCell<Option<gtk::ToggleButton>>,
-        pub modified: Cell<bool>,
+        pub outline_btn: RefCell<Option<gtk::ToggleButton>>,
+        pub css_provider: RefCell<Option<gtk::CssProvider>>,
+        /// View mode and split ratio new tabs open with.
+        pub view_mode: Cell<ViewMode>,
+        pub split_ratio: Cell<f64>,
         pub close_confirmed: Cell<bool>,
         pub initial_dir: RefCell<PathBuf>,
-        pub close_after_save: Cell<bool>,
-        pub preview_scroll_percent: Cell<f64>,
-        pub editor_scrolled: RefCell<Option<gtk::ScrolledWindow>>,
-        pub preview_update_pending: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -48,14 +38,30 @@ mod imp {
         type ParentType = adw::ApplicationWindow;
     }
 
-    impl ObjectImpl for MyMarkdownWindow {}
+    impl ObjectImpl for MyMarkdownWindow {
+        fn dispose(&self) {
+            if let (Some(css_provider), Some(display)) =
+                (self.css_provider.take(), gdk::Display::default())
+            {
+                gtk::style_context_remove_provider_for_display(&display, &css_provider);
+            }
+        }
+    }
     impl WidgetImpl for MyMarkdownWindow {}
     impl WindowImpl for MyMarkdownWindow {
         fn close_request(&self) -> glib::Propagation {
             let window = self.obj();
 
-            // If already confirmed or not modified, allow close
-            if self.close_confirmed.get() || !self.modified.get() {
+            // If already confirmed or nothing is modified, allow close
+            if self.close_confirmed.get() || !window.documents().iter().any(|d| d.is_modified()) {
+                window.save_state();
+                // Closing the last window quits, so this is the session
+                if let Some(app) = window.app().filter(|app| app.windows().len() == 1) {
+                    app.save_session();
+                }
+                for document in window.documents() {
+                    document.discard_recovery();
+                }
                 return glib::Propagation::Proceed;
             }
 
@@ -75,7 +81,7 @@ glib::wrapper! {
 }
 
 impl MyMarkdownWindow {
-    pub fn new(app: &crate::app::MyMarkdownApp, file_arg: Option<String>, initial_dir: PathBuf) -> Self {
+    pub fn new(app: &MyMarkdownApp, initial_dir: PathBuf) -> Self {
         let window: Self = glib::Object::builder()
             .property("application", app)
             .property("default-width", 1200)
@@ -84,27 +90,15 @@ impl MyMarkdownWindow {
 
         // Store initial directory
         window.imp().initial_dir.replace(initial_dir);
+        window.add_css_class("mymd-window");
 
         window.setup_ui();
         window.setup_actions();
+        window.apply_preferences();
+        window.restore_state();
 
-        // Set initial mode to Write
-        window.set_view_mode(ViewMode::Write);
-
-        // Set write button as active
-        if let Some(ref write_btn) = *window.imp().write_btn.borrow() {
-            write_btn.set_active(true);
-        }
-        if let Some(ref split_btn) = *window.imp().split_btn.borrow() {
-            split_btn.set_active(false);
-        }
-
-        // Handle file argument
-        if let Some(filename) = file_arg {
-            window.handle_file_arg(&filename);
-        } else {
-            window.set_title(Some("Untitled - MyMarkdown"));
-        }
+        // Show the view mode new tabs will open in
+        window.sync_view_buttons(window.imp().view_mode.get());
 
         window
     }
@@ -118,9 +112,11 @@ impl MyMarkdownWindow {
         // Header bar
         let header = adw::HeaderBar::new();
 
-        // Title widget
-        let title = adw::WindowTitle::new("MyMarkdown", "");
-        header.set_title_widget(Some(&title));
+        // Outline sidebar toggle
+        let outline_btn = gtk::ToggleButton::new();
+        outline_btn.set_icon_name("sidebar-show-symbolic");
+        outline_btn.set_tooltip_text(Some("Outline (F9)"));
+        header.pack_start(&outline_btn);
 
         // New button
         let new_btn = gtk::Button::from_icon_name("document-new-symbolic");
@@ -166,240 +162,113 @@ impl MyMarkdownWindow {
         menu_btn.set_tooltip_text(Some("Menu"));
 
         let menu = gio::Menu::new();
+        let recent_menu = gio::Menu::new();
+        let themes_menu = gio::Menu::new();
+        menu.append_submenu(Some("Open Recent"), &recent_menu);
+        menu.append_submenu(Some("Preview Theme"), &themes_menu);
+        menu.append(Some("Preferences"), Some("win.preferences"));
         menu.append(Some("About"), Some("win.about"));
         menu_btn.set_menu_model(Some(&menu));
+
+        // Recent files and themes change while the app runs, so list them
+        // on every open
+        Self::fill_recent_menu(&recent_menu);
+        Self::fill_themes_menu(&themes_menu);
+        menu_btn.connect_active_notify(move |menu_btn| {
+            if menu_btn.is_active() {
+                Self::fill_recent_menu(&recent_menu);
+                Self::fill_themes_menu(&themes_menu);
+            }
+        });
         header.pack_end(&menu_btn);
 
         main_box.append(&header);
 
-        // Create paned view for editor and preview
-        let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
-        paned.set_vexpand(true);
-        paned.set_hexpand(true);
-        paned.set_shrink_start_child(false);
-        paned.set_shrink_end_child(false);
-        paned.set_resize_start_child(true);
-        paned.set_resize_end_child(true);
-
-        // Editor side
-        let editor_frame = self.create_editor();
-        paned.set_start_child(Some(&editor_frame));
-
-        // Preview side
-        let preview_frame = self.create_preview();
-        paned.set_end_child(Some(&preview_frame));
-
-        // Set initial position to 50%
-        paned.set_position(600);
-
-        imp.paned.replace(Some(paned.clone()));
-        imp.editor_frame.replace(Some(editor_frame));
-        imp.preview_frame.replace(Some(preview_frame));
-        imp.write_btn.replace(Some(write_btn.clone()));
-        imp.preview_btn.replace(Some(preview_btn.clone()));
-        imp.split_btn.replace(Some(split_btn.clone()));
-
-        main_box.append(&paned);
-
-        self.set_content(Some(&main_box));
-
-        // Connect signals
-        self.connect_signals(&new_btn, &open_btn, &save_btn, &write_btn, &preview_btn, &split_btn);
-    }
-
-    fn create_editor(&self) -> gtk::Frame {
-        let imp = self.imp();
-
-        let frame = gtk::Frame::new(None);
-        frame.add_css_class("view");
+        // One tab per open document; the tab bar only shows up with 2+ tabs
+        let tab_view = adw::TabView::new();
+        tab_view.set_vexpand(true);
+        tab_view.set_hexpand(true);
 
-        let scrolled = gtk::ScrolledWindow::new();
-        scrolled.set_vexpand(true);
-        scrolled.set_hexpand(true);
+        let tab_bar = adw::TabBar::new();
+        tab_bar.set_view(Some(&tab_view));
+        tab_bar.set_autohide(true);
 
-        // Create source view with markdown language
-        let buffer = sourceview::Buffer::new(None);
+        main_box.append(&tab_bar);
+        main_box.append(&tab_view);
 
-        // Set markdown language for syntax highlighting
-        let lang_manager = sourceview::LanguageManager::default();
-        if let Some(lang) = lang_manager.language("markdown") {
-            buffer.set_language(Some(&lang));
-        }
+        imp.tab_view.replace(Some(tab_view.clone()));
+        imp.write_btn.replace(Some(write_btn.clone()));
+        imp.preview_btn.replace(Some(preview_btn.clone()));
+        imp.split_btn.replace(Some(split_btn.clone()));
+        imp.outline_btn.replace(Some(outline_btn.clone()));
 
-        // Use Adwaita-dark scheme as base (more neutral colors)
-        let scheme_manager = sourceview::StyleSchemeManager::default();
-        if let Some(scheme) = scheme_manager.scheme("Adwaita-dark")
-            .or_else(|| scheme_manager.scheme("classic-dark"))
-        {
-            buffer.set_style_scheme(Some(&scheme));
-        }
+        let toast_overlay = adw::ToastOverlay::new();
+        toast_overlay.set_child(Some(&main_box));
+        imp.toast_overlay.replace(Some(toast_overlay.clone()));
+        self.set_content(Some(&toast_overlay));
 
-        let source_view = sourceview::View::with_buffer(&buffer);
-        source_view.set_monospace(true);
-        source_view.set_show_line_numbers(true);
-        source_view.set_highlight_current_line(true);
-        source_view.set_tab_width(4);
-        source_view.set_indent_width(4);
-        source_view.set_auto_indent(true);
-        source_view.set_insert_spaces_instead_of_tabs(true);
-        source_view.set_smart_backspace(true);
-        source_view.set_wrap_mode(gtk::WrapMode::Word);
-        source_view.set_left_margin(12);
-        source_view.set_right_margin(12);
-        source_view.set_top_margin(12);
-        source_view.set_bottom_margin(12);
-
-        // Apply Yaru dark orange theme via CSS
+        // Accent styling, scoped to our windows through the mymd-window class
         let css_provider = gtk::CssProvider::new();
-        css_provider.load_from_string(
-            r#"
-            /* Toggle buttons (Write/Preview) - orange when active */
-            .linked button:checked {
-                background: #E95420;
-                color: #ffffff;
-            }
-
-            /* Split button when active */
-            headerbar button:checked {
-                background: #E95420;
-                color: #ffffff;
-            }
-
-            /* Paned separator - orange accent */
-            paned > separator {
-                background-color: #E95420;
-                min-width: 2px;
-                min-height: 2px;
-            }
-
-            /* Line numbers gutter - dark charcoal with orange text */
-            .source-view gutter {
-                background-color: #252525;
-                padding: 0 8px;
-            }
-
-            .source-view .line-numbers {
-                background-color: #252525;
-                color: #E95420;
-                font-weight: 500;
-            }
-
-            /* Text selection - orange */
-            textview text selection {
-                background-color: #E95420;
-                color: #ffffff;
-            }
-
-            /* Caret color */
-            textview text {
-                caret-color: #E95420;
-            }
-
-            /* Scrollbar hover - orange */
-            scrollbar slider:hover {
-                background-color: #E95420;
-            }
-            "#,
-        );
         gtk::style_context_add_provider_for_display(
             &gdk::Display::default().unwrap(),
             &css_provider,
             gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
         );
+        imp.css_provider.replace(Some(css_provider));
 
-        // Setup paste handler for plain text
-        self.setup_paste_handler(&source_view);
-
-        // Connect buffer changed signal for live preview and modified tracking
-        let window = self.clone();
-        buffer.connect_changed(move |_| {
-            window.imp().modified.set(true);
-            window.schedule_preview_update();
-        });
-
-        // Connect scroll event for sync in split mode
-        let vadjustment = scrolled.vadjustment();
+        // The outline is shown or hidden in every tab at once
         let window = self.clone();
-        vadjustment.connect_value_changed(move |adj| {
-            let imp = window.imp();
-            if imp.view_mode.get() == ViewMode::Split && !imp.updating.get() {
-                let upper = adj.upper() - adj.page_size();
-                if upper > 0.0 {
-                    let percent = adj.value() / upper;
-                    window.sync_preview_scroll(percent);
-                }
+        outline_btn.connect_toggled(move |btn| {
+            for document in window.documents() {
+                document.set_outline_visible(btn.is_active());
             }
         });
 
-        scrolled.set_child(Some(&source_view));
-        frame.set_child(Some(&scrolled));
-
-        imp.source_view.replace(Some(source_view));
-        imp.editor_scrolled.replace(Some(scrolled));
-        frame
+        // Connect signals
+        self.connect_signals(&new_btn, &open_btn, &save_btn, &write_btn, &preview_btn, &split_btn);
+        self.connect_tab_signals(&tab_view);
     }
 
-    fn create_preview(&self) -> gtk::Frame {
-        let imp = self.imp();
-
-        let frame = gtk::Frame::new(None);
-        frame.add_css_class("view");
-
-        let scrolled = gtk::ScrolledWindow::new();
-        scrolled.set_vexpand(true);
-        scrolled.set_hexpand(true);
-
-        // Create webkit view for preview
-        let web_view = webkit::WebView::new();
-        web_view.set_vexpand(true);
-        web_view.set_hexpand(true);
-
-        // Configure WebView settings (enable JS for scroll sync)
-        if let Some(settings) = webkit::prelude::WebViewExt::settings(&web_view) {
-            settings.set_enable_write_console_messages_to_stdout(false);
-            settings.set_enable_developer_extras(false);
-            settings.set_enable_javascript(true);  // Needed for scroll sync
-            settings.set_enable_javascript_markup(false);
-        }
-
-        // Load initial empty content
-        self.load_preview_content(&web_view, "");
-
-        scrolled.set_child(Some(&web_view));
-        frame.set_child(Some(&scrolled));
-
-        imp.web_view.replace(Some(web_view));
-        frame
-    }
+    fn connect_tab_signals(&self, tab_view: &adw::TabView) {
+        // Reflect the selected document in the header and title
+        let window = self.clone();
+        tab_view.connect_selected_page_notify(move |_| {
+            if let Some(document) = window.current_document() {
+                window.sync_view_buttons(document.view_mode());
+                document.focus_editor();
+            }
+            window.update_title();
+        });
 
-    fn setup_paste_handler(&self, source_view: &sourceview::View) {
-        // Override paste to always use plain text
-        let controller = gtk::EventControllerKey::new();
-        let view = source_view.clone();
+        // Ask before closing a tab with unsaved changes
+        tab_view.connect_close_page(|tab_view, page| {
+            let document = page.child().downcast::<MyMarkdownDocument>().unwrap();
+            document.save_position();
+            if !document.is_modified() {
+                document.discard_recovery();
+                return glib::Propagation::Proceed;
+            }
 
-        controller.connect_key_pressed(move |_, key, _, modifier| {
-            // Check for Ctrl+V
-            if modifier.contains(gdk::ModifierType::CONTROL_MASK)
-                && (key == gdk::Key::v || key == gdk::Key::V)
-            {
-                let clipboard = view.clipboard();
-                let view_clone = view.clone();
-
-                // Read plain text from clipboard
-                clipboard.read_text_async(None::<&gio::Cancellable>, move |result| {
-                    if let Ok(Some(text)) = result {
-                        let buffer = view_clone.buffer();
-                        buffer.delete_selection(true, true);
-                        buffer.insert_at_cursor(&text);
-                    }
-                });
+            let tab_view = tab_view.clone();
+            let page = page.clone();
+            let closing = document.clone();
+            document.show_close_confirmation(move |confirmed| {
+                if confirmed {
+                    closing.discard_recovery();
+                }
+                tab_view.close_page_finish(&page, confirmed);
+            });
+            glib::Propagation::Stop
+        });
 
-                return glib::Propagation::Stop;
+        // Closing the last tab closes the window
+        let window = self.clone();
+        tab_view.connect_n_pages_notify(move |tab_view| {
+            if tab_view.n_pages() == 0 {
+                window.imp().close_confirmed.set(true);
+                window.close();
             }
-            glib::Propagation::Proceed
         });
-
-        source_view.add_controller(controller);
     }
 
     fn connect_signals(
@@ -426,7 +295,9 @@ impl MyMarkdownWindow {
         // Save button
         let window = self.clone();
         save_btn.connect_clicked(move |_| {
-            window.save_file();
+            if let Some(document) = window.current_document() {
+                document.save_file();
+            }
         });
 
         // Write button
@@ -510,7 +381,9 @@ impl MyMarkdownWindow {
         let action = gio::SimpleAction::new("save", None);
         let window = self.clone();
         action.connect_activate(move |_, _| {
-            window.save_file();
+            if let Some(document) = window.current_document() {
+                document.save_file();
+            }
         });
         self.add_action(&action);
         app.set_accels_for_action("win.save", &["<Ctrl>s"]);
@@ -519,11 +392,25 @@ impl MyMarkdownWindow {
         let action = gio::SimpleAction::new("save-as", None);
         let window = self.clone();
         action.connect_activate(move |_, _| {
-            window.save_file_as();
+            if let Some(document) = window.current_document() {
+                document.save_file_as();
+            }
         });
         self.add_action(&action);
         app.set_accels_for_action("win.save-as", &["<Ctrl><Shift>s"]);
 
+        // Ctrl+W - Close tab
+        let action = gio::SimpleAction::new("close-tab", None);
+        let window = self.clone();
+        action.connect_activate(move |_, _| {
+            let tab_view = window.tab_view();
+            if let Some(page) = tab_view.selected_page() {
+                tab_view.close_page(&page);
+            }
+        });
+        self.add_action(&action);
+        app.set_accels_for_action("win.close-tab", &["<Ctrl>w"]);
+
         // Ctrl+1 - Write mode
         let action = gio::SimpleAction::new("write-mode", None);
         let window = self.clone();
@@ -557,6 +444,64 @@ impl MyMarkdownWindow {
         self.add_action(&action);
         app.set_accels_for_action("win.toggle-split", &["<Ctrl>backslash"]);
 
+        // F9 - Toggle outline
+        let action = gio::SimpleAction::new("toggle-outline", None);
+        let window = self.clone();
+        action.connect_activate(move |_, _| {
+            if let Some(ref outline_btn) = *window.imp().outline_btn.borrow() {
+                outline_btn.set_active(!outline_btn.is_active());
+            }
+        });
+        self.add_action(&action);
+        app.set_accels_for_action("win.toggle-outline", &["F9"]);
+
+        // Preview theme, "" for the bundled light/dark one or a CSS file path
+        let action = gio::SimpleAction::new_stateful(
+            "preview-theme",
+            Some(glib::VariantTy::STRING),
+            &"".to_variant(),
+        );
+        let window = self.clone();
+        action.connect_change_state(move |_, value| {
+            let Some(path) = value.and_then(|value| value.get::<String>()) else {
+                return;
+            };
+            // The state follows the preferences, see apply_preferences
+            if let Some(app) = window.app() {
+                app.update_preferences(|preferences| {
+                    preferences.preview_theme = PreviewTheme::from_setting(&path);
+                });
+            }
+        });
+        self.add_action(&action);
+
+        // Open a file from the Open Recent menu
+        let action = gio::SimpleAction::new("open-recent", Some(glib::VariantTy::STRING));
+        let window = self.clone();
+        action.connect_activate(move |_, value| {
+            if let Some(path) = value.and_then(|value| value.get::<String>()) {
+                window.open_file(std::path::Path::new(&path));
+            }
+        });
+        self.add_action(&action);
+
+        // Open the folder user themes are read from
+        let action = gio::SimpleAction::new("open-themes-folder", None);
+        let window = self.clone();
+        action.connect_activate(move |_, _| {
+            window.open_themes_folder();
+        });
+        self.add_action(&action);
+
+        // Ctrl+, - Preferences
+        let action = gio::SimpleAction::new("preferences", None);
+        let window = self.clone();
+        action.connect_activate(move |_, _| {
+            window.show_preferences();
+        });
+        self.add_action(&action);
+        app.set_accels_for_action("win.preferences", &["<Ctrl>comma"]);
+
         // About action
         let action = gio::SimpleAction::new("about", None);
         let window = self.clone();
@@ -566,254 +511,183 @@ impl MyMarkdownWindow {
         self.add_action(&action);
     }
 
-    fn set_view_mode(&self, mode: ViewMode) {
-        let imp = self.imp();
-        let old_mode = imp.view_mode.get();
+    fn tab_view(&self) -> adw::TabView {
+        self.imp().tab_view.borrow().clone().unwrap()
+    }
 
-        // Save preview scroll position before leaving Preview mode
-        if old_mode == ViewMode::Preview || old_mode == ViewMode::Split {
-            self.save_preview_scroll();
-        }
+    pub fn current_document(&self) -> Option<MyMarkdownDocument> {
+        self.tab_view()
+            .selected_page()
+            .and_then(|page| page.child().downcast::<MyMarkdownDocument>().ok())
+    }
 
-        imp.view_mode.set(mode);
-
-        if let (Some(editor_frame), Some(preview_frame), Some(paned)) = (
-            &*imp.editor_frame.borrow(),
-            &*imp.preview_frame.borrow(),
-            &*imp.paned.borrow(),
-        ) {
-            match mode {
-                ViewMode::Write => {
-                    editor_frame.set_visible(true);
-                    preview_frame.set_visible(false);
-                }
-                ViewMode::Preview => {
-                    editor_frame.set_visible(false);
-                    preview_frame.set_visible(true);
-                    self.update_preview();
-                    // Restore scroll position after content loads
-                    let window = self.clone();
-                    glib::timeout_add_local_once(std::time::Duration::from_millis(50), move || {
-                        window.restore_preview_scroll();
-                    });
-                }
-                ViewMode::Split => {
-                    editor_frame.set_visible(true);
-                    preview_frame.set_visible(true);
-                    // Set position after window is realized
-                    let paned_clone = paned.clone();
-                    glib::idle_add_local_once(move || {
-                        let width = paned_clone.width();
-                        if width > 0 {
-                            paned_clone.set_position(width / 2);
-                        } else {
-                            // Fallback to default
-                            paned_clone.set_position(600);
-                        }
-                    });
-                    self.update_preview();
-                    // Sync to editor scroll position
-                    let window = self.clone();
-                    glib::timeout_add_local_once(std::time::Duration::from_millis(50), move || {
-                        window.sync_preview_to_editor();
-                    });
-                }
-            }
-        }
+    pub fn documents(&self) -> Vec<MyMarkdownDocument> {
+        let tab_view = self.tab_view();
+        (0..tab_view.n_pages())
+            .filter_map(|i| tab_view.nth_page(i).child().downcast::<MyMarkdownDocument>().ok())
+            .collect()
     }
 
-    fn save_preview_scroll(&self) {
-        if let Some(ref web_view) = *self.imp().web_view.borrow() {
-            let window = self.clone();
-            web_view.evaluate_javascript(
-                "(function() { var h = document.body.scrollHeight - window.innerHeight; return h > 0 ? window.scrollY / h : 0; })()",
-                None,
-                None,
-                None::<&gio::Cancellable>,
-                move |result| {
-                    if let Ok(value) = result {
-                        let percent = value.to_double();
-                        window.imp().preview_scroll_percent.set(percent);
-                    }
-                },
-            );
+    /// Adds `document` as a new tab and selects it.
+    pub fn add_document(&self, document: &MyMarkdownDocument) {
+        let tab_view = self.tab_view();
+        // New tabs split like the current one
+        let split_ratio = self.split_ratio();
+        let page = tab_view.append(document);
+
+        if let Some(ref outline_btn) = *self.imp().outline_btn.borrow() {
+            document.set_outline_visible(outline_btn.is_active());
+        }
+        if let Some(app) = self.app() {
+            document.apply_preferences(app.preferences());
         }
+        document.set_split_ratio(split_ratio);
+        document.set_view_mode(self.imp().view_mode.get());
+
+        let window = self.clone();
+        document.connect_changed(move |document| {
+            window.update_tab(document);
+        });
+        self.update_tab(document);
+
+        tab_view.set_selected_page(&page);
     }
 
-    fn restore_preview_scroll(&self) {
-        let percent = self.imp().preview_scroll_percent.get();
-        if percent > 0.0 {
-            self.sync_preview_scroll(percent);
+    /// Opens a file passed on the command line or by the desktop launcher.
+    pub fn open_path(&self, path: PathBuf) {
+        if self.select_document_for(&path) {
+            return;
+        }
+
+        let initial_dir = self.imp().initial_dir.borrow().clone();
+        match MyMarkdownDocument::for_path(path, initial_dir) {
+            Ok(document) => self.replace_pristine_document(&document),
+            Err(error) => self.show_file_error(&error),
         }
     }
 
-    fn sync_preview_scroll(&self, percent: f64) {
-        if let Some(ref web_view) = *self.imp().web_view.borrow() {
-            let js = format!(
-                "window.scrollTo(0, (document.body.scrollHeight - window.innerHeight) * {});",
-                percent
-            );
-            web_view.evaluate_javascript(
-                &js,
-                None,
-                None,
-                None::<&gio::Cancellable>,
-                |_| {},
-            );
+    /// Selects the tab already showing `path`, if there is one.
+    fn select_document_for(&self, path: &std::path::Path) -> bool {
+        let existing = self.documents().into_iter().find(|d| d.file().as_deref() == Some(path));
+        match existing {
+            Some(document) => {
+                let tab_view = self.tab_view();
+                tab_view.set_selected_page(&tab_view.page(&document));
+                true
+            }
+            None => false,
         }
     }
 
-    fn sync_preview_to_editor(&self) {
-        let imp = self.imp();
-        if let Some(ref scrolled) = *imp.editor_scrolled.borrow() {
-            let adj = scrolled.vadjustment();
-            let upper = adj.upper() - adj.page_size();
-            if upper > 0.0 {
-                let percent = adj.value() / upper;
-                self.sync_preview_scroll(percent);
-            }
+    /// Adds `document`, closing the current tab if it is an untouched "Untitled".
+    fn replace_pristine_document(&self, document: &MyMarkdownDocument) {
+        let pristine = self.current_document().filter(|d| d.is_pristine());
+        self.add_document(document);
+        if let Some(pristine) = pristine {
+            let tab_view = self.tab_view();
+            tab_view.close_page(&tab_view.page(&pristine));
         }
     }
 
-    fn handle_file_arg(&self, filename: &str) {
-        let path = if filename.ends_with(".md") {
-            PathBuf::from(filename)
-        } else {
-            PathBuf::from(format!("{}.md", filename))
-        };
+    fn update_tab(&self, document: &MyMarkdownDocument) {
+        let page = self.tab_view().page(document);
+        page.set_title(&document.title());
+        if let Some(path) = document.file() {
+            page.set_tooltip(&path.to_string_lossy());
+        }
 
-        if path.exists() {
-            // Open existing file
-            self.load_file(&path);
+        if document.is_modified() {
+            page.set_indicator_icon(Some(&gio::ThemedIcon::new("document-modified-symbolic")));
+            page.set_indicator_tooltip("Unsaved changes");
         } else {
-            // Create new file
-            self.imp().current_file.replace(Some(path.clone()));
+            page.set_indicator_icon(None::<&gio::Icon>);
+        }
+
+        if self.current_document().as_ref() == Some(document) {
+            self.sync_view_buttons(document.view_mode());
             self.update_title();
         }
     }
 
-    fn load_file(&self, path: &PathBuf) {
-        match fs::read_to_string(path) {
-            Ok(content) => {
-                if let Some(ref source_view) = *self.imp().source_view.borrow() {
-                    let buffer = source_view.buffer();
-                    buffer.set_text(&content);
-                }
-                self.imp().current_file.replace(Some(path.clone()));
-                self.update_title();
-                // Reset modified after loading
-                self.imp().modified.set(false);
-            }
-            Err(e) => {
-                eprintln!("Error loading file: {}", e);
-            }
+    fn set_view_mode(&self, mode: ViewMode) {
+        self.imp().view_mode.set(mode);
+        if let Some(document) = self.current_document() {
+            document.set_view_mode(mode);
         }
     }
 
-    fn save_file(&self) {
+    /// Split ratio of the current tab, or the one the window started with.
+    fn split_ratio(&self) -> f64 {
+        self.current_document()
+            .map(|document| document.split_ratio())
+            .unwrap_or_else(|| self.imp().split_ratio.get())
+    }
+
+    /// Sizes the window and picks the view mode from the last session.
+    fn restore_state(&self) {
         let imp = self.imp();
+        imp.split_ratio.set(0.5);
 
-        if let Some(ref path) = *imp.current_file.borrow() {
-            self.write_file(path);
-        } else {
-            self.save_file_as();
+        let Some(settings) = self.app().and_then(|app| app.settings()) else {
+            return;
+        };
+        self.set_default_size(settings.int("window-width"), settings.int("window-height"));
+        if settings.boolean("window-maximized") {
+            self.maximize();
+        }
+        if let Some(mode) = ViewMode::from_name(&settings.string("view-mode")) {
+            imp.view_mode.set(mode);
         }
+        imp.split_ratio.set(settings.double("split-ratio"));
     }
 
-    fn save_file_as(&self) {
-        let dialog = gtk::FileDialog::new();
-        dialog.set_title("Save As");
-
-        let filter = gtk::FileFilter::new();
-        filter.add_pattern("*.md");
-        filter.set_name(Some("Markdown files"));
-
-        let filters = gio::ListStore::new::<gtk::FileFilter>();
-        filters.append(&filter);
-        dialog.set_filters(Some(&filters));
-
-        // Set initial folder
-        let initial_dir = self.imp().initial_dir.borrow().clone();
-        let initial_folder = gio::File::for_path(&initial_dir);
-        dialog.set_initial_folder(Some(&initial_folder));
-
-        let window = self.clone();
-        dialog.save(Some(&window.clone()), None::<&gio::Cancellable>, move |result| {
-            if let Ok(file) = result {
-                if let Some(path) = file.path() {
-                    let path = if path.extension().is_none() {
-                        path.with_extension("md")
-                    } else {
-                        path
-                    };
-                    window.imp().current_file.replace(Some(path.clone()));
-                    window.write_file(&path);
-                    window.update_title();
-
-                    // If close was requested after save, close now
-                    if window.imp().close_after_save.get() {
-                        window.imp().close_after_save.set(false);
-                        window.imp().close_confirmed.set(true);
-                        window.close();
-                    }
-                }
-            } else {
-                // User cancelled save dialog, reset close_after_save flag
-                window.imp().close_after_save.set(false);
-            }
-        });
-    }
+    /// Saves the window state and the position in each open file.
+    fn save_state(&self) {
+        for document in self.documents() {
+            document.save_position();
+        }
 
-    fn write_file(&self, path: &PathBuf) {
-        if let Some(ref source_view) = *self.imp().source_view.borrow() {
-            let buffer = source_view.buffer();
-            let start = buffer.start_iter();
-            let end = buffer.end_iter();
-            let text = buffer.text(&start, &end, false);
-
-            if let Err(e) = fs::write(path, text.as_str()) {
-                eprintln!("Error saving file: {}", e);
-            } else {
-                // Reset modified flag after successful save
-                self.imp().modified.set(false);
+        let Some(settings) = self.app().and_then(|app| app.settings()) else {
+            return;
+        };
+        // The default size is the unmaximized size
+        let (width, height) = self.default_size();
+        let results = [
+            settings.set_int("window-width", width),
+            settings.set_int("window-height", height),
+            settings.set_boolean("window-maximized", self.is_maximized()),
+            settings.set_string("view-mode", self.imp().view_mode.get().name()),
+            settings.set_double("split-ratio", self.split_ratio()),
+        ];
+        for result in results {
+            if let Err(e) = result {
+                eprintln!("Error saving window state: {}", e);
             }
         }
     }
 
-    fn new_file(&self) {
-        // Check for unsaved changes first
-        if self.imp().modified.get() {
-            self.show_discard_confirmation(|win| {
-                win.do_new_file();
-            });
-        } else {
-            self.do_new_file();
+    /// Updates the Write/Preview/Split buttons without switching modes.
+    fn sync_view_buttons(&self, mode: ViewMode) {
+        let imp = self.imp();
+        let was_updating = imp.updating.replace(true);
+        if let Some(ref write_btn) = *imp.write_btn.borrow() {
+            write_btn.set_active(mode == ViewMode::Write);
         }
-    }
-
-    fn do_new_file(&self) {
-        self.imp().current_file.replace(None);
-        self.imp().modified.set(false);
-        if let Some(ref source_view) = *self.imp().source_view.borrow() {
-            source_view.buffer().set_text("");
+        if let Some(ref preview_btn) = *imp.preview_btn.borrow() {
+            preview_btn.set_active(mode == ViewMode::Preview);
         }
-        self.set_title(Some("Untitled - MyMarkdown"));
-        // Reset modified after setting empty text
-        self.imp().modified.set(false);
+        if let Some(ref split_btn) = *imp.split_btn.borrow() {
+            split_btn.set_active(mode == ViewMode::Split);
+        }
+        imp.updating.set(was_updating);
     }
 
-    fn open_file_dialog(&self) {
-        // Check for unsaved changes first
-        if self.imp().modified.get() {
-            self.show_discard_confirmation(|win| {
-                win.do_open_file_dialog();
-            });
-        } else {
-            self.do_open_file_dialog();
-        }
+    pub fn new_file(&self) {
+        let initial_dir = self.imp().initial_dir.borrow().clone();
+        self.add_document(&MyMarkdownDocument::new(initial_dir));
     }
 
-    fn do_open_file_dialog(&self) {
+    fn open_file_dialog(&self) {
         let dialog = gtk::FileDialog::new();
         dialog.set_title("Open File");
 
@@ -828,258 +702,250 @@ impl MyMarkdownWindow {
 
         let window = self.clone();
         dialog.open(Some(&window.clone()), None::<&gio::Cancellable>, move |result| {
-            if let Ok(file) = result {
-                if let Some(path) = file.path() {
-                    window.load_file(&path);
-                }
+            if let Ok(file) = result
+                && let Some(path) = file.path()
+            {
+                window.open_file(&path);
             }
         });
     }
 
+    /// Opens an existing file in a new tab, or focuses the tab showing it.
+    pub fn open_file(&self, path: &std::path::Path) {
+        if self.select_document_for(path) {
+            return;
+        }
+
+        let initial_dir = self.imp().initial_dir.borrow().clone();
+        let document = MyMarkdownDocument::new(initial_dir);
+        match document.load_file(path) {
+            Ok(()) => self.replace_pristine_document(&document),
+            Err(error) => self.show_file_error(&error),
+        }
+    }
+
+    /// Shows a brief notification at the bottom of the window.
+    pub fn show_toast(&self, message: &str) {
+        if let Some(ref toast_overlay) = *self.imp().toast_overlay.borrow() {
+            // Toast titles are markup
+            toast_overlay.add_toast(adw::Toast::new(&glib::markup_escape_text(message)));
+        }
+    }
+
+    pub fn show_file_error(&self, error: &FileError) {
+        eprintln!("{}", error);
+        self.show_toast(&error.message());
+    }
+
     fn update_title(&self) {
-        if let Some(ref path) = *self.imp().current_file.borrow() {
-            let filename = path.file_name().unwrap_or_default().to_string_lossy();
-            self.set_title(Some(&format!("{} - MyMarkdown", filename)));
+        match self.current_document() {
+            Some(document) => self.set_title(Some(&format!("{} - MyMarkdown", document.title()))),
+            None => self.set_title(Some("MyMarkdown")),
         }
     }
 
-    fn schedule_preview_update(&self) {
-        let imp = self.imp();
+    fn app(&self) -> Option<MyMarkdownApp> {
+        self.application().and_downcast::<MyMarkdownApp>()
+    }
 
-        // Skip if already pending or in write mode
-        if imp.preview_update_pending.get() || imp.view_mode.get() == ViewMode::Write {
+    /// Applies the app preferences to this window and its documents.
+    pub fn apply_preferences(&self) {
+        let Some(app) = self.app() else {
             return;
+        };
+
+        let preferences = app.preferences();
+        for document in self.documents() {
+            document.apply_preferences(preferences.clone());
         }
 
-        imp.preview_update_pending.set(true);
+        if let Some(action) = self
+            .lookup_action("preview-theme")
+            .and_downcast::<gio::SimpleAction>()
+        {
+            action.set_state(&preferences.preview_theme.to_setting().to_variant());
+        }
 
-        // Debounce: wait 150ms before updating
-        let window = self.clone();
-        glib::timeout_add_local_once(std::time::Duration::from_millis(150), move || {
-            window.imp().preview_update_pending.set(false);
-            window.update_preview_with_scroll_preserve();
-        });
+        if let Some(ref css_provider) = *self.imp().css_provider.borrow() {
+            css_provider.load_from_string(&accent_css(preferences.accent_color()));
+        }
     }
 
-    fn update_preview_with_scroll_preserve(&self) {
-        let imp = self.imp();
-        let mode = imp.view_mode.get();
+    fn show_preferences(&self) {
+        if let Some(app) = self.app() {
+            preferences::dialog(&app).present(Some(self));
+        }
+    }
+
+    /// Lists the most recently used Markdown files, newest first.
+    fn fill_recent_menu(recent_menu: &gio::Menu) {
+        recent_menu.remove_all();
 
-        if mode == ViewMode::Write {
+        let files = recent_files();
+        if files.is_empty() {
+            // Without an action the item shows as disabled
+            recent_menu.append(Some("No Recent Files"), None);
             return;
         }
-
-        // Save current scroll position before update
-        if mode == ViewMode::Split {
-            // Get current editor scroll percentage
-            if let Some(ref scrolled) = *imp.editor_scrolled.borrow() {
-                let adj = scrolled.vadjustment();
-                let upper = adj.upper() - adj.page_size();
-                if upper > 0.0 {
-                    imp.preview_scroll_percent.set(adj.value() / upper);
-                }
-            }
+        for path in files {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let item = gio::MenuItem::new(Some(&name), None);
+            item.set_action_and_target_value(
+                Some("win.open-recent"),
+                Some(&path.to_string_lossy().to_variant()),
+            );
+            recent_menu.append_item(&item);
         }
+    }
 
-        // Do the actual update
-        self.update_preview();
+    /// Reopens the files open when the app last quit, returning whether
+    /// any could be.
+    pub fn restore_session(&self) -> bool {
+        let Some(settings) = self.app().and_then(|app| app.settings()) else {
+            return false;
+        };
 
-        // Restore scroll position after a short delay (for content to load)
-        if mode == ViewMode::Split || mode == ViewMode::Preview {
-            let window = self.clone();
-            glib::timeout_add_local_once(std::time::Duration::from_millis(50), move || {
-                window.restore_preview_scroll();
-            });
+        let paths: Vec<PathBuf> = settings
+            .strv("session-files")
+            .iter()
+            .map(|path| PathBuf::from(path.as_str()))
+            .filter(|path| path.is_file())
+            .collect();
+        for path in &paths {
+            self.open_file(path);
         }
+        !self.documents().is_empty()
     }
 
-    fn update_preview(&self) {
-        let imp = self.imp();
-        let mode = imp.view_mode.get();
+    /// Offers to restore documents autosaved by a session that ended without
+    /// saving or closing them.
+    pub fn offer_recovery(&self) {
+        self.ask_recovery(recovery::snapshots());
+    }
 
-        if mode == ViewMode::Write {
+    /// Asks about the first of `snapshots`, then moves on to the rest.
+    fn ask_recovery(&self, mut snapshots: Vec<Snapshot>) {
+        if snapshots.is_empty() {
             return;
         }
+        let snapshot = snapshots.remove(0);
 
-        if let Some(ref source_view) = *imp.source_view.borrow() {
-            let buffer = source_view.buffer();
-            let start = buffer.start_iter();
-            let end = buffer.end_iter();
-            let text = buffer.text(&start, &end, false);
+        let saved = glib::DateTime::from_unix_local(snapshot.saved as i64)
+            .and_then(|time| time.format("%x %X"))
+            .map(|time| format!(" on {}", time))
+            .unwrap_or_default();
+        let dialog = adw::AlertDialog::builder()
+            .heading("Recover Unsaved Changes?")
+            .body(format!(
+                "“{}” had unsaved changes when MyMarkdown last quit. They were autosaved{}.",
+                snapshot_name(&snapshot),
+                saved
+            ))
+            // Escape keeps the copy, to ask again next time
+            .close_response("later")
+            .default_response("restore")
+            .build();
 
-            if let Some(ref web_view) = *imp.web_view.borrow() {
-                self.load_preview_content(web_view, &text);
-            }
+        dialog.add_response("discard", "Discard");
+        if snapshot.path.as_deref().is_some_and(|path| path.is_file()) {
+            dialog.add_response("compare", "Compare…");
         }
+        dialog.add_response("restore", "Restore");
+        dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+        dialog.set_response_appearance("restore", adw::ResponseAppearance::Suggested);
+
+        let window = self.clone();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
+            match response.as_str() {
+                "restore" => window.restore_snapshot(snapshot),
+                "discard" => recovery::remove(&snapshot.id),
+                "compare" => {
+                    // Ask about the same copy again once the diff is closed
+                    snapshots.insert(0, snapshot.clone());
+                    window.show_recovery_diff(&snapshot, snapshots);
+                    return;
+                }
+                _ => {}
+            }
+            window.ask_recovery(snapshots);
+        });
     }
 
-    fn load_preview_content(&self, web_view: &webkit::WebView, markdown: &str) {
-        // Parse markdown to HTML
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_FOOTNOTES);
-        options.insert(Options::ENABLE_TASKLISTS);
-        options.insert(Options::ENABLE_SMART_PUNCTUATION);
-
-        let parser = Parser::new_ext(markdown, options);
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-
-        // Wrap in HTML template with styling (Yaru dark orange theme)
-        let full_html = format!(
-            r#"<!DOCTYPE html>
-<html>
-<head>
-    <meta charset="UTF-8">
-    <style>
-        :root {{
-            color-scheme: dark;
-        }}
-
-        body {{
-            font-family: "Cantarell", "Ubuntu", "Segoe UI", sans-serif;
-            font-size: 15px;
-            line-height: 1.6;
-            padding: 24px;
-            max-width: 100%;
-            margin: 0;
-            background: #1d1d1d;
-            color: #f0f0f0;
-        }}
-
-        h1, h2, h3, h4, h5, h6 {{
-            margin-top: 1.5em;
-            margin-bottom: 0.5em;
-            font-weight: 600;
-            color: #ffffff;
-        }}
-
-        h1 {{ font-size: 2em; border-bottom: 2px solid #E95420; padding-bottom: 0.3em; }}
-        h2 {{ font-size: 1.5em; border-bottom: 1px solid #3d3d3d; padding-bottom: 0.3em; }}
-        h3 {{ font-size: 1.25em; }}
-        h4 {{ font-size: 1em; }}
-
-        p {{
-            margin: 1em 0;
-        }}
-
-        code {{
-            font-family: "JetBrainsMono Nerd Font", "JetBrains Mono", "Ubuntu Mono", monospace;
-            font-size: 0.9em;
-            background: #2d2d2d;
-            color: #ff7043;
-            padding: 0.2em 0.4em;
-            border-radius: 4px;
-            word-break: break-word;
-        }}
-
-        pre {{
-            background: #2d2d2d;
-            padding: 16px;
-            border-radius: 8px;
-            border-left: 3px solid #E95420;
-            overflow-x: auto;
-            white-space: pre-wrap;
-            word-wrap: break-word;
-        }}
-
-        pre code {{
-            background: none;
-            padding: 0;
-            color: #f0f0f0;
-            white-space: pre-wrap;
-            word-wrap: break-word;
-            display: block;
-        }}
-
-        blockquote {{
-            margin: 1em 0;
-            padding: 0.5em 1em;
-            border-left: 4px solid #E95420;
-            background: #252525;
-            color: #b0b0b0;
-        }}
-
-        a {{
-            color: #E95420;
-            text-decoration: none;
-        }}
-
-        a:hover {{
-            color: #ff7043;
-            text-decoration: underline;
-        }}
-
-        ul, ol {{
-            padding-left: 2em;
-        }}
-
-        li {{
-            margin: 0.5em 0;
-        }}
-
-        li::marker {{
-            color: #E95420;
-        }}
-
-        table {{
-            border-collapse: collapse;
-            width: 100%;
-            margin: 1em 0;
-        }}
-
-        table th, table td {{
-            border: 1px solid #3d3d3d;
-            padding: 8px 12px;
-            text-align: left;
-        }}
-
-        table th {{
-            font-weight: 600;
-            background: #E95420;
-            color: #ffffff;
-        }}
-
-        tr:nth-child(even) {{
-            background: #252525;
-        }}
-
-        hr {{
-            border: none;
-            border-top: 2px solid #E95420;
-            margin: 2em 0;
-        }}
-
-        img {{
-            max-width: 100%;
-            height: auto;
-            border-radius: 8px;
-        }}
-
-        ::selection {{
-            background: #E95420;
-            color: #ffffff;
-        }}
-
-        /* Task list */
-        ul.task-list {{
-            list-style: none;
-            padding-left: 1em;
-        }}
-
-        input[type="checkbox"] {{
-            margin-right: 0.5em;
-        }}
-    </style>
-</head>
-<body>
-{html_output}
-</body>
-</html>"#
+    /// Shows how an autosaved copy differs from its file on disk.
+    fn show_recovery_diff(&self, snapshot: &Snapshot, snapshots: Vec<Snapshot>) {
+        let on_disk = match snapshot.path.as_deref().map(files::read) {
+            Some(Ok(text)) => text,
+            Some(Err(error)) => {
+                self.show_file_error(&error);
+                String::new()
+            }
+            None => String::new(),
+        };
+        let style_scheme = self.app().and_then(|app| app.preferences().style_scheme);
+        let is_dark = adw::StyleManager::default().is_dark();
+        let dialog = compare::dialog(
+            &format!("Changes to “{}”", snapshot_name(snapshot)),
+            ("On Disk", &on_disk),
+            ("Recovered", &snapshot.text),
+            style_scheme_for(style_scheme.as_deref(), is_dark).as_ref(),
         );
 
-        web_view.load_html(&full_html, None);
+        let window = self.clone();
+        let snapshots = Cell::new(Some(snapshots));
+        dialog.connect_closed(move |_| {
+            if let Some(snapshots) = snapshots.take() {
+                window.ask_recovery(snapshots);
+            }
+        });
+        dialog.present(Some(self));
+    }
+
+    /// Opens an autosaved copy, in the tab for its file if one is open.
+    fn restore_snapshot(&self, snapshot: Snapshot) {
+        let open = snapshot
+            .path
+            .as_deref()
+            .is_some_and(|path| self.select_document_for(path));
+        match self.current_document() {
+            Some(document) if open => document.restore_snapshot(snapshot),
+            _ => {
+                let initial_dir = self.imp().initial_dir.borrow().clone();
+                let document = MyMarkdownDocument::for_snapshot(snapshot, initial_dir);
+                self.replace_pristine_document(&document);
+            }
+        }
+    }
+
+    /// Lists the bundled theme and the user themes as radio items.
+    fn fill_themes_menu(themes_menu: &gio::Menu) {
+        themes_menu.remove_all();
+
+        let themes = gio::Menu::new();
+        for preview_theme in std::iter::once(PreviewTheme::System).chain(theme::custom_themes()) {
+            let target = preview_theme.to_setting();
+            let item = gio::MenuItem::new(Some(&preview_theme.name()), None);
+            item.set_action_and_target_value(Some("win.preview-theme"), Some(&target.to_variant()));
+            themes.append_item(&item);
+        }
+        themes_menu.append_section(None, &themes);
+        themes_menu.append(Some("Open Themes Folder"), Some("win.open-themes-folder"));
+    }
+
+    fn open_themes_folder(&self) {
+        let Some(dir) = theme::themes_dir() else {
+            return;
+        };
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            eprintln!("Error creating themes folder: {}", e);
+            return;
+        }
+
+        let launcher = gtk::FileLauncher::new(Some(&gio::File::for_path(&dir)));
+        launcher.launch(Some(self), None::<&gio::Cancellable>, |result| {
+            if let Err(e) = result {
+                eprintln!("Error opening themes folder: {}", e);
+            }
+        });
     }
 
     fn show_about(&self) {
@@ -1095,60 +961,24 @@ impl MyMarkdownWindow {
         about.present(Some(self));
     }
 
-    fn show_discard_confirmation<F>(&self, on_continue: F)
-    where
-        F: Fn(&Self) + 'static,
-    {
-        let dialog = adw::AlertDialog::builder()
-            .heading("Discard Changes?")
-            .body("You have unsaved changes. Do you want to save them first?")
-            .close_response("cancel")
-            .default_response("save")
-            .build();
-
-        dialog.add_response("cancel", "Cancel");
-        dialog.add_response("discard", "Discard");
-        dialog.add_response("save", "Save");
-
-        dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
-        dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
-
-        let window = self.clone();
-        dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
-            match response.as_str() {
-                "save" => {
-                    // Save first, then continue
-                    if window.imp().current_file.borrow().is_some() {
-                        window.save_file();
-                        on_continue(&window);
-                    } else {
-                        // Need to save as - this is async, so we can't easily chain
-                        // For simplicity, just save and let user try again
-                        window.save_file_as();
-                    }
-                }
-                "discard" => {
-                    window.imp().modified.set(false);
-                    on_continue(&window);
-                }
-                _ => {
-                    // Cancel - do nothing
-                }
-            }
-        });
-    }
-
     fn show_close_confirmation(&self) {
+        let unsaved = self.documents().iter().filter(|d| d.is_modified()).count();
+        let body = if unsaved == 1 {
+            "One document has unsaved changes. Do you want to save it before closing?".to_string()
+        } else {
+            format!("{} documents have unsaved changes. Do you want to save them before closing?", unsaved)
+        };
+
         let dialog = adw::AlertDialog::builder()
             .heading("Save Changes?")
-            .body("Do you want to save changes before closing?")
+            .body(body)
             .close_response("cancel")
             .default_response("save")
             .build();
 
         dialog.add_response("cancel", "Cancel");
         dialog.add_response("discard", "Don't Save");
-        dialog.add_response("save", "Save");
+        dialog.add_response("save", if unsaved == 1 { "Save" } else { "Save All" });
 
         dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
         dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
@@ -1157,16 +987,7 @@ impl MyMarkdownWindow {
         dialog.choose(Some(self), None::<&gio::Cancellable>, move |response| {
             match response.as_str() {
                 "save" => {
-                    // Set flag so save_file_as knows to close after save
-                    if window.imp().current_file.borrow().is_none() {
-                        window.imp().close_after_save.set(true);
-                    }
-                    window.save_file();
-                    // For existing files, close immediately
-                    if window.imp().current_file.borrow().is_some() && !window.imp().close_after_save.get() {
-                        window.imp().close_confirmed.set(true);
-                        window.close();
-                    }
+                    window.save_all_then_close();
                 }
                 "discard" => {
                     window.imp().close_confirmed.set(true);
@@ -1178,4 +999,111 @@ impl MyMarkdownWindow {
             }
         });
     }
+
+    /// Saves every modified document in turn, then closes the window.
+    ///
+    /// Stops without closing if a save fails or a Save As dialog is cancelled.
+    fn save_all_then_close(&self) {
+        match self.documents().into_iter().find(|d| d.is_modified()) {
+            Some(document) => {
+                let tab_view = self.tab_view();
+                tab_view.set_selected_page(&tab_view.page(&document));
+
+                let window = self.clone();
+                document.save_then(move |saved| {
+                    if saved {
+                        window.save_all_then_close();
+                    }
+                });
+            }
+            None => {
+                self.imp().close_confirmed.set(true);
+                self.close();
+            }
+        }
+    }
+}
+
+/// Window styling in the accent color: the view toggles, the paned
+/// separator, line numbers, selection and caret.
+///
+/// Without a custom color the libadwaita accent is used, which follows the
+/// system accent. A custom color overrides it for our windows only.
+fn accent_css(color: Option<gdk::RGBA>) -> String {
+    let mut css = String::new();
+    if let Some(color) = color {
+        css.push_str(&format!(
+            "window.mymd-window {{ --accent-bg-color: {color}; --accent-color: {color}; }}\n"
+        ));
+    }
+    css.push_str(
+        r#"
+        window.mymd-window .linked button:checked,
+        window.mymd-window headerbar button:checked {
+            background: var(--accent-bg-color);
+            color: var(--accent-fg-color);
+        }
+
+        window.mymd-window paned > separator {
+            background-color: var(--accent-bg-color);
+            min-width: 2px;
+            min-height: 2px;
+        }
+
+        window.mymd-window .source-view .line-numbers {
+            color: var(--accent-color);
+            font-weight: 500;
+        }
+
+        window.mymd-window textview text selection {
+            background-color: var(--accent-bg-color);
+            color: var(--accent-fg-color);
+        }
+
+        window.mymd-window textview text {
+            caret-color: var(--accent-color);
+        }
+
+        window.mymd-window scrollbar slider:hover {
+            background-color: var(--accent-bg-color);
+        }
+        "#,
+    );
+    css
+}
+
+/// File name of the document an autosaved copy belongs to.
+fn snapshot_name(snapshot: &Snapshot) -> String {
+    snapshot
+        .path
+        .as_deref()
+        .and_then(|path| path.file_name())
+        .map_or_else(|| "Untitled".to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Number of files in the Open Recent menu.
+const RECENT_LIMIT: usize = 10;
+
+/// Markdown files from the desktop's recently used list that still exist.
+fn recent_files() -> Vec<PathBuf> {
+    let mut items: Vec<gtk::RecentInfo> = gtk::RecentManager::default()
+        .items()
+        .into_iter()
+        .filter(|info| info.is_local() && info.exists())
+        .filter(|info| {
+            let uri = info.uri();
+            matches!(info.mime_type().as_str(), "text/markdown" | "text/x-markdown")
+                || uri.ends_with(".md")
+                || uri.ends_with(".markdown")
+        })
+        .collect();
+    items.sort_by_key(|info| {
+        std::cmp::Reverse(info.modified().to_unix())
+    });
+
+    items
+        .iter()
+        .filter_map(|info| gio::File::for_uri(&info.uri()).path())
+        .take(RECENT_LIMIT)
+        .collect()
 }
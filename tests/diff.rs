@@ -0,0 +1,35 @@
+use mymd::diff::changed_lines;
+
+#[test]
+fn identical_texts_have_no_changes() {
+    assert_eq!(changed_lines("a\nb\n", "a\nb\n"), (vec![], vec![]));
+    assert_eq!(changed_lines("", ""), (vec![], vec![]));
+}
+
+#[test]
+fn edited_lines_are_marked_on_both_sides() {
+    let old = "one\ntwo\nthree\nfour\n";
+    let new = "one\n2\nthree\nfour\n";
+    assert_eq!(changed_lines(old, new), (vec![1], vec![1]));
+}
+
+#[test]
+fn insertions_and_deletions_shift_the_other_side() {
+    // `b` removed, `x` and `y` added after `c`
+    let old = "a\nb\nc\nd\n";
+    let new = "a\nc\nx\ny\nd\n";
+    assert_eq!(changed_lines(old, new), (vec![1], vec![2, 3]));
+}
+
+#[test]
+fn separate_changes_are_found_apart() {
+    let old: String = (0..20).map(|n| format!("line {}\n", n)).collect();
+    let new = old.replace("line 2\n", "changed\n").replace("line 17\n", "");
+    assert_eq!(changed_lines(&old, &new), (vec![2, 17], vec![2]));
+}
+
+#[test]
+fn empty_sides_are_all_added_or_removed() {
+    assert_eq!(changed_lines("", "a\nb"), (vec![], vec![0, 1]));
+    assert_eq!(changed_lines("a\nb", ""), (vec![0, 1], vec![]));
+}
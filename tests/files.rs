@@ -0,0 +1,162 @@
+use mymd::files::{self, Backup};
+use std::fs;
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+
+/// An empty folder of its own for each test, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("mymd-files-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn write_creates_a_new_file() {
+    let scratch = Scratch::new("new");
+    let path = scratch.path("notes.md");
+
+    files::write(&path, "# Notes\n", Backup::None).unwrap();
+    assert_eq!(files::read(&path).unwrap(), "# Notes\n");
+    // No temporary file is left behind
+    assert_eq!(fs::read_dir(&scratch.0).unwrap().count(), 1);
+}
+
+#[test]
+fn simple_backup_keeps_the_previous_version() {
+    let scratch = Scratch::new("simple");
+    let path = scratch.path("notes.md");
+
+    files::write(&path, "one", Backup::Simple).unwrap();
+    assert!(!scratch.path("notes.md~").exists());
+    files::write(&path, "two", Backup::Simple).unwrap();
+    files::write(&path, "three", Backup::Simple).unwrap();
+
+    assert_eq!(read(&path), "three");
+    assert_eq!(read(&scratch.path("notes.md~")), "two");
+}
+
+#[test]
+fn numbered_backups_count_up() {
+    let scratch = Scratch::new("numbered");
+    let path = scratch.path("notes.md");
+
+    for text in ["one", "two", "three"] {
+        files::write(&path, text, Backup::Numbered).unwrap();
+    }
+
+    assert_eq!(read(&path), "three");
+    assert_eq!(read(&scratch.path("notes.md.~1~")), "one");
+    assert_eq!(read(&scratch.path("notes.md.~2~")), "two");
+    assert!(!scratch.path("notes.md.~3~").exists());
+}
+
+#[test]
+fn symlinks_are_saved_through() {
+    let scratch = Scratch::new("symlink");
+    fs::create_dir(scratch.path("real")).unwrap();
+    let target = scratch.path("real/notes.md");
+    let link = scratch.path("notes.md");
+    fs::write(&target, "old").unwrap();
+    symlink("real/notes.md", &link).unwrap();
+
+    files::write(&link, "new", Backup::Simple).unwrap();
+
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(read(&target), "new");
+    assert_eq!(read(&scratch.path("real/notes.md~")), "old");
+}
+
+#[test]
+fn dangling_symlinks_create_their_target() {
+    let scratch = Scratch::new("dangling");
+    let link = scratch.path("notes.md");
+    symlink("drafts.md", &link).unwrap();
+
+    files::write(&link, "new", Backup::None).unwrap();
+
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(read(&scratch.path("drafts.md")), "new");
+}
+
+#[test]
+fn permissions_are_kept() {
+    let scratch = Scratch::new("permissions");
+    let path = scratch.path("notes.md");
+    fs::write(&path, "old").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+    files::write(&path, "new", Backup::None).unwrap();
+
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+}
+
+#[test]
+fn private_files_stay_private() {
+    let scratch = Scratch::new("private");
+    let path = scratch.path("notes.md");
+    fs::write(&path, "old").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+    files::write(&path, "secret", Backup::None).unwrap();
+
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn errors_name_the_file() {
+    let scratch = Scratch::new("errors");
+    let path = scratch.path("gone/notes.md");
+
+    let error = files::write(&path, "text", Backup::None).unwrap_err();
+    assert_eq!(error.message(), "The folder for “notes.md” no longer exists.");
+    let error = files::read(&path).unwrap_err();
+    assert_eq!(error.message(), "“notes.md” no longer exists.");
+}
+
+/// `text` with its trailing whitespace removed.
+fn trimmed(text: &str) -> String {
+    let mut kept = String::new();
+    let mut start = 0;
+    for range in files::trailing_whitespace(text) {
+        kept.push_str(&text[start..range.start]);
+        start = range.end;
+    }
+    kept.push_str(&text[start..]);
+    kept
+}
+
+#[test]
+fn trailing_whitespace_is_found_at_line_ends() {
+    assert_eq!(files::trailing_whitespace("a \nb\t\t\nc"), [1..2, 4..6]);
+    assert_eq!(trimmed("one \t\ntwo\r\n   \nlast  "), "one\ntwo\r\n\nlast");
+    assert!(files::trailing_whitespace("clean\ntext\n").is_empty());
+}
+
+#[test]
+fn hard_breaks_keep_two_spaces() {
+    assert_eq!(trimmed("break  \nnext"), "break  \nnext");
+    assert_eq!(trimmed("break   \t\nnext"), "break  \nnext");
+    assert_eq!(trimmed("break  \r\nnext"), "break  \r\nnext");
+    // Not a break: a blank line, the last line or a single space
+    assert_eq!(trimmed("  \nend  "), "\nend");
+    assert_eq!(trimmed("one \nnext"), "one\nnext");
+}
@@ -0,0 +1,50 @@
+//! Renders each `tests/golden/NAME.md` and compares the body with
+//! `tests/golden/NAME.html`.
+//!
+//! After an intended change to the output, regenerate the expected files
+//! with `MYMD_BLESS=1 cargo test --test golden` and review the diff.
+
+use mymd::diagram::{Diagrams, MermaidBackend};
+use mymd::render::Renderer;
+use std::fs;
+use std::path::Path;
+
+/// The default renderer, minus the diagram programs, which may or may not be
+/// installed where the tests run.
+fn renderer() -> Renderer {
+    let mut diagrams = Diagrams::new();
+    diagrams.register(MermaidBackend);
+    Renderer::new().with_diagrams(diagrams)
+}
+
+#[test]
+fn rendered_html_matches_the_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let bless = std::env::var_os("MYMD_BLESS").is_some();
+    let renderer = renderer();
+
+    let mut inputs: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no fixtures in {}", dir.display());
+
+    let mut mismatched = Vec::new();
+    for input in &inputs {
+        let html = renderer.render_body(&fs::read_to_string(input).unwrap());
+        let expected_path = input.with_extension("html");
+        if bless {
+            fs::write(&expected_path, &html).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if html != expected {
+            eprintln!("--- {}\n{}", expected_path.display(), html);
+            mismatched.push(input.file_name().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    assert!(mismatched.is_empty(), "output changed for {:?}", mismatched);
+}
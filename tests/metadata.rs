@@ -0,0 +1,86 @@
+use mymd::metadata::{Format, FrontMatter, format_value};
+
+#[test]
+fn yaml_fields_are_read_with_their_ranges() {
+    let markdown = "---\ntitle: \"Say \\\"hi\\\"\"\ntags: [a, b]\nauthor: 'O''Neil'\nnested:\n  key: x\n# comment\n---\n\n# Body\n";
+    let front_matter = FrontMatter::parse(markdown).unwrap();
+
+    assert_eq!(front_matter.format, Format::Yaml);
+    let keys: Vec<&str> = front_matter.fields.iter().map(|field| field.key.as_str()).collect();
+    assert_eq!(keys, ["title", "tags", "author"]);
+    assert_eq!(front_matter.title(), Some("Say \"hi\""));
+    assert_eq!(front_matter.get("tags"), Some("[a, b]"));
+    assert_eq!(front_matter.get("author"), Some("O'Neil"));
+
+    let title = &front_matter.fields[0];
+    assert_eq!(&markdown[title.value_range.clone()], "\"Say \\\"hi\\\"\"");
+}
+
+#[test]
+fn toml_fields_stop_at_the_first_table() {
+    let markdown = "+++\ntitle = \"Notes\"\ndraft = true\n[extra]\nkey = 1\n+++\n";
+    let front_matter = FrontMatter::parse(markdown).unwrap();
+
+    assert_eq!(front_matter.format, Format::Toml);
+    assert_eq!(front_matter.title(), Some("Notes"));
+    assert_eq!(front_matter.get("draft"), Some("true"));
+    assert_eq!(front_matter.get("key"), None);
+}
+
+#[test]
+fn documents_without_front_matter_have_none() {
+    assert_eq!(FrontMatter::parse("# Title\n\n---\ntitle: no\n---\n"), None);
+    assert_eq!(FrontMatter::parse(""), None);
+    // A blank title does not count
+    let front_matter = FrontMatter::parse("---\ntitle: ' '\n---\n").unwrap();
+    assert_eq!(front_matter.title(), None);
+}
+
+#[test]
+fn yaml_values_keep_their_quoting() {
+    assert_eq!(format_value(Format::Yaml, "old", "plain text"), "plain text");
+    assert_eq!(format_value(Format::Yaml, "\"old\"", "plain"), "\"plain\"");
+    assert_eq!(format_value(Format::Yaml, "'old'", "it's"), "'it''s'");
+    // Values YAML would read differently get quoted
+    assert_eq!(format_value(Format::Yaml, "old", "a: b"), "\"a: b\"");
+    assert_eq!(format_value(Format::Yaml, "old", "#tag"), "\"#tag\"");
+    assert_eq!(format_value(Format::Yaml, "old", ""), "\"\"");
+    assert_eq!(format_value(Format::Yaml, "old", "[a, b]"), "[a, b]");
+}
+
+#[test]
+fn toml_strings_are_always_quoted() {
+    assert_eq!(format_value(Format::Toml, "\"old\"", "say \"hi\""), "\"say \\\"hi\\\"\"");
+    assert_eq!(format_value(Format::Toml, "1", "2.5"), "2.5");
+    assert_eq!(format_value(Format::Toml, "false", "true"), "true");
+    assert_eq!(format_value(Format::Toml, "1", "text"), "\"text\"");
+}
+
+#[test]
+fn values_spanning_several_lines_are_left_out() {
+    let markdown = "---\ntitle: Notes\nsummary: |\n  First line\n  Second line\nfolded: >-\n  text\nwrapped: one\n  two\ntags: [a]\n---\n";
+    let front_matter = FrontMatter::parse(markdown).unwrap();
+
+    assert_eq!(front_matter.title(), Some("Notes"));
+    assert_eq!(front_matter.get("summary"), None);
+    assert_eq!(front_matter.get("folded"), None);
+    assert_eq!(front_matter.get("wrapped"), None);
+    assert_eq!(front_matter.get("tags"), Some("[a]"));
+
+    let markdown = "+++\ntitle = 'Notes'\nbody = \"\"\"\ntext\n\"\"\"\nlist = [\n  1,\n]\n+++\n";
+    let front_matter = FrontMatter::parse(markdown).unwrap();
+
+    assert_eq!(front_matter.title(), Some("Notes"));
+    assert_eq!(front_matter.get("body"), None);
+    assert_eq!(front_matter.get("list"), None);
+}
+
+#[test]
+fn single_quotes_unescape_by_format() {
+    let yaml = FrontMatter::parse("---\ntitle: 'it''s'\n---\n").unwrap();
+    assert_eq!(yaml.title(), Some("it's"));
+
+    // TOML literal strings have no escapes
+    let toml = FrontMatter::parse("+++\ntitle = 'C:\\dir''s'\n+++\n").unwrap();
+    assert_eq!(toml.title(), Some("C:\\dir''s"));
+}
@@ -0,0 +1,36 @@
+use mymd::recovery;
+use std::path::Path;
+
+#[test]
+fn copies_are_listed_until_removed() {
+    let data = std::env::temp_dir().join(format!("mymd-recovery-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data);
+    // The only test in this binary, so nothing else reads the environment
+    unsafe { std::env::set_var("XDG_DATA_HOME", &data) };
+    assert!(recovery::snapshots().is_empty());
+
+    let notes = recovery::new_id();
+    let untitled = recovery::new_id();
+    assert_ne!(notes, untitled);
+    recovery::save(&notes, Some(Path::new("/home/me/notes.md")), "# Notes\n\nText\n").unwrap();
+    recovery::save(&untitled, None, "draft").unwrap();
+    // A second save replaces the copy
+    recovery::save(&untitled, None, "draft 2").unwrap();
+
+    let mut snapshots = recovery::snapshots();
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0].id, notes);
+    assert_eq!(snapshots[0].path.as_deref(), Some(Path::new("/home/me/notes.md")));
+    assert_eq!(snapshots[0].text, "# Notes\n\nText\n");
+    assert!(snapshots[0].saved > 0);
+    assert_eq!(snapshots[1].path, None);
+    assert_eq!(snapshots[1].text, "draft 2");
+
+    recovery::remove(&notes);
+    recovery::remove(&notes);
+    let ids: Vec<String> = recovery::snapshots().into_iter().map(|snapshot| snapshot.id).collect();
+    assert_eq!(ids, [untitled]);
+
+    let _ = std::fs::remove_dir_all(&data);
+}
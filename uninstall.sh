@@ -14,9 +14,11 @@ echo ""
 if [ "$EUID" -eq 0 ]; then
     INSTALL_DIR="/usr/local/bin"
     DESKTOP_DIR="/usr/share/applications"
+    SCHEMA_DIR="/usr/local/share/glib-2.0/schemas"
 else
     INSTALL_DIR="$HOME/.local/bin"
     DESKTOP_DIR="$HOME/.local/share/applications"
+    SCHEMA_DIR="$HOME/.local/share/glib-2.0/schemas"
 fi
 
 # Remove binary
@@ -46,6 +48,18 @@ else
     echo -e "${YELLOW}Desktop file not found in $DESKTOP_DIR.${NC}"
 fi
 
+# Remove settings schema
+if [ -f "$SCHEMA_DIR/org.gnome.MyMarkdown.gschema.xml" ]; then
+    echo -e "${YELLOW}Removing settings schema from $SCHEMA_DIR...${NC}"
+    rm -f "$SCHEMA_DIR/org.gnome.MyMarkdown.gschema.xml"
+    if command -v glib-compile-schemas &> /dev/null; then
+        glib-compile-schemas "$SCHEMA_DIR"
+    fi
+    echo -e "${GREEN}Settings schema removed.${NC}"
+else
+    echo -e "${YELLOW}Settings schema not found in $SCHEMA_DIR.${NC}"
+fi
+
 # Update desktop database
 if command -v update-desktop-database &> /dev/null; then
     update-desktop-database "$DESKTOP_DIR" 2>/dev/null || true